@@ -0,0 +1,46 @@
+use crate::{*, utils::*};
+
+use tygres_macros::builder;
+builder! {
+    struct CopyInBuilder {
+        source: F[Source],
+        values: V as setting(Val: ColumnsSetter<F>),
+    }
+}
+
+impl<F: Source, V: ColumnsSetter<F>> CopyInBuilder<F, Wrap<V>> {
+    // `SELECT <columns> FROM <source> LIMIT 0`, whose result columns are
+    // the types to encode the values as.
+    pub(crate) fn push_types_sql(&self, buf: &mut String) {
+        buf.push_str("SELECT ");
+        self.values.0.push_selection(buf);
+        buf.push_str(" FROM ");
+        self.source.push_source(buf);
+        buf.push_str(" LIMIT 0");
+    }
+}
+
+impl<F: Source, V: ColumnsSetter<F>> IntoSql for CopyInBuilder<F, Wrap<V>> {
+    type Get = Unit;
+    type Set = V::Set;
+
+    fn check(&self) -> Result<(), Error> {
+        if !self.values.0.push_selection(&mut String::new()) {
+            return Err(Error::EmptySelection);
+        }
+        self.values.0.check()
+    }
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("COPY ");
+        self.source.push_source(buf);
+        buf.push_str(" (");
+        self.values.0.push_selection(buf);
+        buf.push_str(") FROM STDIN (FORMAT binary)");
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, self.values.0.into_types())
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{*, utils::*, source::Typed};
+
+// Schema statements built from the same `Source` and `Column` types the
+// queries use.  They take no parameters, so their `Get` and `Set` are
+// both `Unit`, and they run like any other statement.
+
+use tygres_macros::builder;
+builder! {
+    struct CreateTableBuilder {
+        source: F[Source],
+        columns: C as columns(Cs: ColumnDefs<F>),
+        primary_key: K as primary_key(Ks: IndexKeys<F>),
+        if_not_exists: E as *if_not_exists(Ine),
+    }
+}
+
+builder! {
+    struct AlterTableBuilder {
+        source: F[Source],
+        actions: A as altering(Act: AlterAction<F>),
+    }
+}
+
+builder! {
+    struct CreateIndexBuilder {
+        source: F[Source],
+        name: N as *named(Nm),
+        keys: K as on(Key: IndexKeys<F>),
+        where_clause: W as filter(Cl: Clause<F>),
+        unique: U as *uniquely(Uq),
+        if_not_exists: E as *if_not_exists(Ine),
+    }
+}
+
+builder! {
+    struct DropTableBuilder {
+        source: F[Source],
+        if_exists: E as *if_exists(Ie),
+        cascade: C as *cascading(Cas),
+    }
+}
+
+pub trait Flag {
+    fn is_set(&self) -> bool;
+}
+
+impl Flag for Unit {
+    #[inline]
+    fn is_set(&self) -> bool { false }
+}
+
+impl Flag for bool {
+    #[inline]
+    fn is_set(&self) -> bool { *self }
+}
+
+// `name type [NOT NULL]`, for each of the columns.
+pub trait ColumnDefs<F: Source> {
+    fn push_defs(&self, buf: &mut String);
+}
+
+impl<F: Source, C: Column<F> + Typed> ColumnDefs<F> for ColWrap<C> {
+    #[inline]
+    fn push_defs(&self, buf: &mut String) {
+        self.0.push_name(buf);
+        buf.push_str(" ");
+        buf.push_str(C::SQL_TYPE);
+        if !C::NULLABLE {
+            buf.push_str(" NOT NULL");
+        }
+    }
+}
+
+impl<F: Source, A: ColumnDefs<F>, B: ColumnDefs<F>> ColumnDefs<F> for Seq<A, B> {
+    #[inline]
+    fn push_defs(&self, buf: &mut String) {
+        self.0.push_defs(buf);
+        buf.push_str(", ");
+        self.1.push_defs(buf);
+    }
+}
+
+// The columns of a key or an index.  A `&'static str` is an expression,
+// pushed as raw sql without quoting or checks: it's the escape hatch for
+// keys like `lower(email)`, so never build one from untrusted input.
+pub trait IndexKeys<F: Source> {
+    fn push_keys(&self, buf: &mut String);
+}
+
+impl<F: Source, C: Column<F>> IndexKeys<F> for ColWrap<C> {
+    #[inline]
+    fn push_keys(&self, buf: &mut String) {
+        self.0.push_name(buf);
+    }
+}
+
+impl<F: Source> IndexKeys<F> for &'static str {
+    #[inline]
+    fn push_keys(&self, buf: &mut String) {
+        buf.push_str("(");
+        buf.push_str(self);
+        buf.push_str(")");
+    }
+}
+
+impl<F: Source, A: IndexKeys<F>, B: IndexKeys<F>> IndexKeys<F> for Seq<A, B> {
+    #[inline]
+    fn push_keys(&self, buf: &mut String) {
+        self.0.push_keys(buf);
+        buf.push_str(", ");
+        self.1.push_keys(buf);
+    }
+}
+
+pub trait PrimaryKeyClause<F: Source> {
+    fn push_primary_key(&self, buf: &mut String);
+}
+
+impl<F: Source> PrimaryKeyClause<F> for Unit {
+    #[inline]
+    fn push_primary_key(&self, _buf: &mut String) {}
+}
+
+impl<F: Source, K: IndexKeys<F>> PrimaryKeyClause<F> for Wrap<K> {
+    #[inline]
+    fn push_primary_key(&self, buf: &mut String) {
+        buf.push_str(", PRIMARY KEY (");
+        self.0.push_keys(buf);
+        buf.push_str(")");
+    }
+}
+
+impl<F: Source, C: ColumnDefs<F>, K: PrimaryKeyClause<F>, E: Flag> IntoSql
+for CreateTableBuilder<F, Wrap<C>, K, E> {
+    type Get = Unit;
+    type Set = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("CREATE TABLE ");
+        if self.if_not_exists.is_set() {
+            buf.push_str("IF NOT EXISTS ");
+        }
+        self.source.push_source(buf);
+        buf.push_str(" (");
+        self.columns.0.push_defs(buf);
+        self.primary_key.push_primary_key(buf);
+        buf.push_str(")");
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+pub struct AddColumn<C>(pub ColWrap<C>);
+pub struct DropColumn<C>(pub ColWrap<C>);
+
+pub trait AlterAction<F: Source> {
+    fn push_action(&self, buf: &mut String);
+}
+
+impl<F: Source, C: Column<F> + Typed> AlterAction<F> for AddColumn<C> {
+    #[inline]
+    fn push_action(&self, buf: &mut String) {
+        buf.push_str("ADD COLUMN ");
+        <ColWrap<C> as ColumnDefs<F>>::push_defs(&self.0, buf);
+    }
+}
+
+impl<F: Source, C: Column<F>> AlterAction<F> for DropColumn<C> {
+    #[inline]
+    fn push_action(&self, buf: &mut String) {
+        buf.push_str("DROP COLUMN ");
+        (self.0).0.push_name(buf);
+    }
+}
+
+impl<F: Source, A: AlterAction<F>, B: AlterAction<F>> AlterAction<F> for Seq<A, B> {
+    #[inline]
+    fn push_action(&self, buf: &mut String) {
+        self.0.push_action(buf);
+        buf.push_str(", ");
+        self.1.push_action(buf);
+    }
+}
+
+impl<F: Source, A: AlterAction<F>> IntoSql for AlterTableBuilder<F, Wrap<A>> {
+    type Get = Unit;
+    type Set = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("ALTER TABLE ");
+        self.source.push_source(buf);
+        buf.push_str(" ");
+        self.actions.0.push_action(buf);
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+// Values taken by clauses without parameters: `Unit`, or any `Seq` of
+// them (as from `and` and `or`).
+pub trait NoValues {}
+
+impl NoValues for Unit {}
+
+impl<A: NoValues, B: NoValues> NoValues for Seq<A, B> {}
+
+// The predicate of a partial index can't take parameters.
+impl<
+    F: Source,
+    K: IndexKeys<F>,
+    W: WhereClause<F>,
+    U: Flag, E: Flag,
+> IntoSql for CreateIndexBuilder<F, &'static str, Wrap<K>, W, U, E>
+where W::Set: NoValues {
+    type Get = Unit;
+    type Set = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("CREATE ");
+        if self.unique.is_set() {
+            buf.push_str("UNIQUE ");
+        }
+        buf.push_str("INDEX ");
+        if self.if_not_exists.is_set() {
+            buf.push_str("IF NOT EXISTS ");
+        }
+        buf.push_str(self.name);
+        buf.push_str(" ON ");
+        self.source.push_source(buf);
+        buf.push_str(" (");
+        self.keys.0.push_keys(buf);
+        buf.push_str(")");
+        self.where_clause.push_where_clause(buf, idx)
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+impl<F: Source, E: Flag, C: Flag> IntoSql for DropTableBuilder<F, E, C> {
+    type Get = Unit;
+    type Set = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("DROP TABLE ");
+        if self.if_exists.is_set() {
+            buf.push_str("IF EXISTS ");
+        }
+        self.source.push_source(buf);
+        if self.cascade.is_set() {
+            buf.push_str(" CASCADE");
+        }
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+// `DROP INDEX`; indexes aren't sources, so this is just the name.
+pub struct DropIndex {
+    pub name: &'static str,
+    pub if_exists: bool,
+    pub cascade: bool,
+}
+
+#[inline]
+pub fn drop_index(name: &'static str) -> DropIndex {
+    DropIndex { name, if_exists: false, cascade: false }
+}
+
+impl DropIndex {
+    #[inline]
+    pub fn if_exists(self, if_exists: bool) -> Self {
+        DropIndex { if_exists, ..self }
+    }
+
+    #[inline]
+    pub fn cascading(self, cascade: bool) -> Self {
+        DropIndex { cascade, ..self }
+    }
+}
+
+impl IntoSql for DropIndex {
+    type Get = Unit;
+    type Set = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("DROP INDEX ");
+        if self.if_exists {
+            buf.push_str("IF EXISTS ");
+        }
+        buf.push_str(self.name);
+        if self.cascade {
+            buf.push_str(" CASCADE");
+        }
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+            (Name, NAME, "name", String),
+            (Email, EMAIL, "email", nullable String),
+        });
+    }
+    use self::users::*;
+
+    fn render<Q: IntoSql>(query: Q) -> String {
+        let mut sql = String::new();
+        assert_eq!(query.push_sql(&mut sql, 1), 1);
+        sql
+    }
+
+    #[test]
+    fn creates_tables() {
+        assert_eq!(render(Users.create_table().columns(ID)),
+                   "CREATE TABLE users (id int4 NOT NULL)");
+        let query = Users.create_table()
+            .columns(seq!(ID, NAME, EMAIL))
+            .primary_key(seq!(ID, NAME))
+            .if_not_exists(true);
+        assert_eq!(render(query), "CREATE TABLE IF NOT EXISTS users \
+            (id int4 NOT NULL, name text NOT NULL, email text, PRIMARY KEY (id, name))");
+    }
+
+    #[test]
+    fn creates_indexes() {
+        assert_eq!(render(Users.create_index("users_name").on(NAME)),
+                   "CREATE INDEX users_name ON users (name)");
+        let query = Users.create_index("users_email")
+            .on(seq!(ID, "lower(email)"))
+            .filter(EMAIL.is_null::<Users>().not())
+            .uniquely(true)
+            .if_not_exists(true);
+        assert_eq!(render(query), "CREATE UNIQUE INDEX IF NOT EXISTS users_email \
+            ON users (id, (lower(email))) WHERE NOT ( email IS NULL )");
+    }
+
+    #[test]
+    fn alters_and_drops() {
+        let query = Users.alter_table().altering(seq!(AddColumn(EMAIL), DropColumn(NAME)));
+        assert_eq!(render(query), "ALTER TABLE users ADD COLUMN email text, DROP COLUMN name");
+        assert_eq!(render(Users.drop_table().if_exists(true).cascading(true)),
+                   "DROP TABLE IF EXISTS users CASCADE");
+        assert_eq!(render(drop_index("users_name").if_exists(true)),
+                   "DROP INDEX IF EXISTS users_name");
+    }
+}
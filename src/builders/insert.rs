@@ -1,4 +1,5 @@
-use crate::{*, utils::*};
+use crate::{*, utils::*, setters::Shapes};
+use postgres::types::ToSql;
 
 use tygres_macros::builder;
 builder! {
@@ -28,50 +29,138 @@ impl Conflict for &'static str {
 
 
 pub trait InsValue<F: Source> {
+    type Set;
+    fn check(&self, reps: usize) -> Result<(), Error>;
     fn push_values(&self, buf: &mut String, reps: usize, idx: usize) -> usize;
+    fn into_types(self) -> Self::Set;
 }
 
-impl<F: Source, S: ColumnsSetter<F>> InsValue<F> for Wrap<S> {
-    fn push_values(&self, buf: &mut String, reps: usize, idx: usize) -> usize {
-        if reps == 0 {
-            panic!("reps must be a positive integer");
+// The rows of a repeated insert, along with which `OrDefault` cells
+// (in row-major order) were given a value.  The rendered sql depends
+// on the shape, so the prepared statement keeps it (see `Shaped`) and
+// refuses rows of any other.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    rows: usize,
+    mask: Vec<bool>,
+}
+
+impl Shape {
+    pub fn of<'r, A: 'r, S: Shapes<A>, I: IntoIterator<Item = &'r A>>(setter: &S, rows: I) -> Self {
+        let mut shape = Shape { rows: 0, mask: vec![] };
+        for row in rows {
+            setter.push_shape(row, &mut shape.mask);
+            shape.rows += 1;
         }
-        buf.push_str("(");
-        if !self.0.push_selection(buf) {
-            panic!("selection empty");
+        shape
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+}
+
+// The setter of an insert repeated over a `Shape`: takes the rows, once
+// they're checked to have that shape.
+pub struct Shaped<S>(pub Shape, pub S);
+
+impl<'a, A: 'a, S, I> Takes<'a, I> for Shaped<S>
+where S: Takes<'a, A> + Shapes<A>,
+      I: IntoIterator<Item = A>,
+      for<'r> &'r I: IntoIterator<Item = &'r A> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, rows: I, buf: &mut Vec<&'a ToSql>) {
+        for row in rows {
+            self.1.push_values(row, buf);
         }
-        buf.push_str(") VALUES");
-        let mut idx = idx;
-        for i in 0..reps {
-            if i != 0 {
-                buf.push_str(", ");
-            }
-            buf.push_str(" (");
-            let (r_idx, _) = self.0.push_values(buf, idx);
-            idx = r_idx;
-            buf.push_str(")");
+    }
+
+    fn check_values(&self, rows: &I) -> Result<(), Error> {
+        if Shape::of(&self.1, rows) == self.0 {
+            Ok(())
+        } else {
+            Err(Error::ShapeMismatch)
         }
-        idx
     }
 }
 
-impl<F: Source> InsValue<F> for Unit {
+fn check_rows<F: Source, S: ColumnsSetter<F>>(setter: &S, reps: usize) -> Result<(), Error> {
+    if reps == 0 {
+        return Err(Error::InvalidRepetitions(reps));
+    }
+    if !setter.push_selection(&mut String::new()) {
+        return Err(Error::EmptySelection);
+    }
+    setter.check()
+}
+
+fn push_rows<F: Source, S: ColumnsSetter<F>>(
+    setter: &S, buf: &mut String, reps: usize, idx: usize,
+    shape: &mut dyn Iterator<Item = bool>) -> usize {
+
+    buf.push_str("(");
+    setter.push_selection(buf);
+    buf.push_str(") VALUES");
+    let mut idx = idx;
+    for i in 0..reps {
+        if i != 0 {
+            buf.push_str(", ");
+        }
+        buf.push_str(" (");
+        let (r_idx, _) = setter.push_shaped_values(buf, idx, shape);
+        idx = r_idx;
+        buf.push_str(")");
+    }
+    idx
+}
+
+impl<F: Source, S: ColumnsSetter<F>> InsValue<F> for Wrap<S> {
+    type Set = Wrap<S::Set>;
+
+    fn check(&self, reps: usize) -> Result<(), Error> {
+        check_rows(&self.0, reps)
+    }
+
     fn push_values(&self, buf: &mut String, reps: usize, idx: usize) -> usize {
+        push_rows(&self.0, buf, reps, idx, &mut std::iter::empty::<bool>())
+    }
+
+    fn into_types(self) -> Self::Set {
+        Wrap(self.0.into_types())
+    }
+}
+
+impl<F: Source> InsValue<F> for Unit {
+    type Set = Unit;
+
+    // Only one row can be inserted with default values.
+    fn check(&self, reps: usize) -> Result<(), Error> {
         if reps != 1 {
-            panic!("Only one row can be inserted with default values");
+            return Err(Error::InvalidRepetitions(reps));
         }
+        Ok(())
+    }
+
+    fn push_values(&self, buf: &mut String, reps: usize, idx: usize) -> usize {
         buf.push_str(" DEFAULT VALUES");
         idx
     }
+
+    fn into_types(self) -> Self::Set {
+        Unit
+    }
 }
 
-impl<F: Source, V, S: ReturningClause<F>, C: Conflict> IntoSql
-for InsertBuilder<F, Wrap<V>, S, usize, C>
-where Wrap<V>: InsValue<F> {
+impl<F: Source, V: ColumnsSetter<F>, S: ReturningClause<F>, C: Conflict> IntoSql
+for InsertBuilder<F, Wrap<V>, S, usize, C> {
 
-    type Set = SqlInput<Wrap<Reps<V>>, Unit, Unit, Unit>;
+    type Set = SqlInput<Wrap<Reps<V::Set>>, Unit, Unit, Unit>;
     type Get = S;
 
+    fn check(&self) -> Result<(), Error> {
+        self.values.check(self.reps)
+    }
+
     fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
         buf.push_str("INSERT INTO ");
         self.source.push_source(buf);
@@ -85,7 +174,42 @@ where Wrap<V>: InsValue<F> {
         (
             self.selection,
             SqlInput {
-                values: Wrap(Reps(self.reps, self.values.0)),
+                values: Wrap(Reps(self.reps, self.values.0.into_types())),
+                where_clause: Unit,
+                limit: Unit,
+                offset: Unit,
+            }
+        )
+    }
+}
+
+impl<F: Source, V: ColumnsSetter<F>, S: ReturningClause<F>, C: Conflict> IntoSql
+for InsertBuilder<F, Wrap<V>, S, Shape, C> {
+
+    type Set = SqlInput<Wrap<Shaped<V::Set>>, Unit, Unit, Unit>;
+    type Get = S;
+
+    fn check(&self) -> Result<(), Error> {
+        check_rows(&self.values.0, self.reps.rows)
+    }
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("INSERT INTO ");
+        self.source.push_source(buf);
+        let idx = push_rows(
+            &self.values.0, buf, self.reps.rows, idx,
+            &mut self.reps.mask.iter().cloned()
+        );
+        self.conflict.push_conflict(buf);
+        self.selection.push_returning(&self.source, buf);
+        idx
+    }
+
+    fn into_types(self) -> (S, Self::Set) {
+        (
+            self.selection,
+            SqlInput {
+                values: Wrap(Shaped(self.reps, self.values.0.into_types())),
                 where_clause: Unit,
                 limit: Unit,
                 offset: Unit,
@@ -97,9 +221,13 @@ where Wrap<V>: InsValue<F> {
 impl<F: Source, V: InsValue<F>, S: ReturningClause<F>, C: Conflict> IntoSql
 for InsertBuilder<F, V, S, Unit, C> {
 
-    type Set = SqlInput<V, Unit, Unit, Unit>;
+    type Set = SqlInput<V::Set, Unit, Unit, Unit>;
     type Get = S;
 
+    fn check(&self) -> Result<(), Error> {
+        self.values.check(1)
+    }
+
     fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
         buf.push_str("INSERT INTO ");
         self.source.push_source(buf);
@@ -113,7 +241,7 @@ for InsertBuilder<F, V, S, Unit, C> {
         (
             self.selection,
             SqlInput {
-                values: self.values,
+                values: self.values.into_types(),
                 where_clause: Unit,
                 limit: Unit,
                 offset: Unit,
@@ -121,3 +249,58 @@ for InsertBuilder<F, V, S, Unit, C> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setters::OrDefault;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+            (Name, NAME, "name", String),
+        });
+    }
+    use self::users::*;
+
+    fn setter() -> Seq![ColWrap<Id>, OrDefault<ColWrap<Name>>] {
+        seq!(ID, NAME.or_default())
+    }
+
+    fn render(shape: Shape) -> String {
+        let mut sql = String::new();
+        Users.insert().setting(setter()).repeating(shape).push_sql(&mut sql, 1);
+        sql
+    }
+
+    #[test]
+    fn renders_defaults_by_shape() {
+        let name = "name".to_string();
+        let first = vec![Seq(&1, Some(&name)), Seq(&2, None)];
+        let second = vec![Seq(&1, None), Seq(&2, Some(&name))];
+
+        assert_eq!(render(Shape::of(&setter(), &first)),
+                   "INSERT INTO users(id, name) VALUES ($1, $2),  ($3, DEFAULT)");
+        assert_eq!(render(Shape::of(&setter(), &second)),
+                   "INSERT INTO users(id, name) VALUES ($1, DEFAULT),  ($2, $3)");
+    }
+
+    #[test]
+    fn rejects_rows_of_another_shape() {
+        let name = "name".to_string();
+        let first = vec![Seq(&1, Some(&name)), Seq(&2, None)];
+        let shape = Shape::of(&setter(), &first);
+        let (_, set) = Users.insert().setting(setter()).repeating(shape).into_types();
+
+        let same = vec![Seq(&3, Some(&name)), Seq(&4, None)];
+        assert!(set.check_values(&ValueBuilder::new().set(same)).is_ok());
+
+        let swapped = vec![Seq(&3, None), Seq(&4, Some(&name))];
+        match set.check_values(&ValueBuilder::new().set(swapped)) {
+            Err(Error::ShapeMismatch) => {},
+            other => panic!("expected a shape mismatch, got {:?}", other),
+        }
+        let fewer = vec![Seq(&3, Some(&name))];
+        assert!(set.check_values(&ValueBuilder::new().set(fewer)).is_err());
+    }
+}
@@ -3,9 +3,15 @@ mod update;
 mod delete;
 mod value;
 mod select;
+pub mod ddl;
+mod copy;
 
-pub use self::insert::InsertBuilder;
+pub use self::insert::{InsertBuilder, Shape, Shaped};
 pub use self::update::UpdateBuilder;
 pub use self::delete::DeleteBuilder;
 pub use self::value::ValueBuilder;
-pub use self::select::*;
\ No newline at end of file
+pub use self::select::*;
+pub use self::copy::CopyInBuilder;
+pub use self::ddl::{
+    CreateTableBuilder, AlterTableBuilder, CreateIndexBuilder, DropTableBuilder,
+};
\ No newline at end of file
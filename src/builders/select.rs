@@ -1,4 +1,4 @@
-use crate::{*, utils::*};
+use crate::{*, utils::*, copy::{CopyOut, Format, Binary}};
 
 use tygres_macros::builder;
 builder! {
@@ -105,15 +105,20 @@ impl<
     O: OrderByClause<F>, L: Limiting, Of: Offsetting, Suf: Suffix,
 > IntoSql for SelectBuilder<F, Wrap<S>, W, O, L, Of, Suf> {
 
-    type Set = SqlInput<Unit, W::Set, L::Set, Of::Set>;
+    type Set = SqlInput<Wrap<F::Set>, W::Set, L::Set, Of::Set>;
     type Get = Wrap<S>;
 
+    fn check(&self) -> Result<(), Error> {
+        self.source.check()
+    }
+
     fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
         buf.push_str("SELECT ");
         self.selection.0.push_selection(&self.source, buf);
         buf.push_str(" FROM ");
-        self.source.push_source(buf);
+        let idx = self.source.push_from(buf, idx);
         let idx = self.where_clause.push_where_clause(buf, idx);
+        self.order.push_order_by(buf);
         let idx = self.limit.push_limit(buf, idx);
         let idx = self.offset.push_offset(buf, idx);
         self.suffix.push_sql(buf);
@@ -124,7 +129,7 @@ impl<
         (
             self.selection,
             SqlInput {
-                values: Unit,
+                values: Wrap(self.source.into_set()),
                 where_clause: self.where_clause.into_types(),
                 limit: self.limit.to_setter(),
                 offset: self.offset.to_setter(),
@@ -136,13 +141,63 @@ impl<
 
 pub struct CursorQuery<S> {
     pub prepared: String,
-    setter: S,
-    idx: usize,
+    pub(crate) setter: S,
+    pub(crate) idx: usize,
 }
 
 pub struct Fetcher<G> {
     pub name: String,
-    getter: G,
+    pub(crate) getter: G,
+    pub(crate) options: CursorOptions,
+}
+
+// `SCROLL` lets the cursor move backwards; `WITH HOLD` keeps it open
+// past the transaction that declared it.  `batch` is how many rows the
+// cursor iterators fetch at a time.
+#[derive(Clone, Debug)]
+pub struct CursorOptions {
+    scroll: bool,
+    hold: bool,
+    batch: usize,
+}
+
+impl Default for CursorOptions {
+    fn default() -> Self {
+        CursorOptions { scroll: false, hold: false, batch: 100 }
+    }
+}
+
+impl CursorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn scroll(self, scroll: bool) -> Self {
+        CursorOptions { scroll, ..self }
+    }
+
+    pub fn hold(self, hold: bool) -> Self {
+        CursorOptions { hold, ..self }
+    }
+
+    pub fn batch(self, batch: usize) -> Self {
+        CursorOptions { batch: batch.max(1), ..self }
+    }
+
+    #[inline]
+    pub(crate) fn is_scroll(&self) -> bool {
+        self.scroll
+    }
+
+    #[inline]
+    pub(crate) fn is_hold(&self) -> bool {
+        self.hold
+    }
+
+    #[inline]
+    pub(crate) fn batch_size(&self) -> usize {
+        self.batch
+    }
 }
 
 impl<
@@ -150,16 +205,32 @@ impl<
     O: OrderByClause<F>, L: Limiting, Of: Offsetting, Suf: Suffix,
 > SelectBuilder<F, Wrap<S>, W, O, L, Of, Suf> {
 
+    #[inline]
     pub fn into_cursor(self, name: &str)
     -> (
-        CursorQuery<SqlInput<Unit, W::Set, L::Set, Of::Set>>,
+        CursorQuery<SqlInput<Wrap<F::Set>, W::Set, L::Set, Of::Set>>,
+        Fetcher<Wrap<S>>,
+    ) {
+        self.into_cursor_with(name, CursorOptions::default())
+    }
+
+    pub fn into_cursor_with(self, name: &str, options: CursorOptions)
+    -> (
+        CursorQuery<SqlInput<Wrap<F::Set>, W::Set, L::Set, Of::Set>>,
         Fetcher<Wrap<S>>,
     ) {
 
         let mut sql: String = String::with_capacity(0x1000);
         sql.push_str("DECLARE ");
         sql.push_str(name);
-        sql.push_str(" CURSOR FOR ");
+        if options.scroll {
+            sql.push_str(" SCROLL");
+        }
+        sql.push_str(" CURSOR ");
+        if options.hold {
+            sql.push_str("WITH HOLD ");
+        }
+        sql.push_str("FOR ");
         let idx = self.push_sql(&mut sql, 1);
         let (getter, setter) = self.into_types();
 
@@ -170,22 +241,32 @@ impl<
             },
             Fetcher {
                 name: name.to_owned(),
-                getter,
+                getter, options,
             },
         )
 
     }
+
+    #[inline]
+    pub fn copy_out(self, format: Format) -> CopyOut<Self, Format> {
+        CopyOut { query: self, format }
+    }
+
+    // Copies out in the binary format, to be decoded into rows.
+    #[inline]
+    pub fn copy_out_binary(self) -> CopyOut<Self, Binary> {
+        CopyOut { query: self, format: Binary }
+    }
 }
 
+// Sub-queries must get a `Wrap`ped selection, so a cursor (getting
+// `Unit`) is never rendered as one; `idx` is always 1.
 impl<S> IntoSql for CursorQuery<S> {
 
     type Set = S;
     type Get = Unit;
 
-    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
-        if idx != 1 {
-            panic!("cursors can not be sub-queries");
-        }
+    fn push_sql(&self, buf: &mut String, _idx: usize) -> usize {
         buf.push_str(&self.prepared);
         self.idx
     }
@@ -196,20 +277,63 @@ impl<S> IntoSql for CursorQuery<S> {
 }
 
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Next,
+    Prior,
+    First,
+    Last,
+    Absolute(i64),
+    Relative(i64),
+    Forward(u64),
+    Backward(u64),
+    ForwardAll,
+    BackwardAll,
+}
+
+impl Direction {
+    pub fn push_sql(&self, buf: &mut String) {
+        match *self {
+            Direction::Next => buf.push_str("NEXT"),
+            Direction::Prior => buf.push_str("PRIOR"),
+            Direction::First => buf.push_str("FIRST"),
+            Direction::Last => buf.push_str("LAST"),
+            Direction::Absolute(n) => buf.push_str(&format!("ABSOLUTE {}", n)),
+            Direction::Relative(n) => buf.push_str(&format!("RELATIVE {}", n)),
+            Direction::Forward(n) => buf.push_str(&format!("FORWARD {}", n)),
+            Direction::Backward(n) => buf.push_str(&format!("BACKWARD {}", n)),
+            Direction::ForwardAll => buf.push_str("FORWARD ALL"),
+            Direction::BackwardAll => buf.push_str("BACKWARD ALL"),
+        }
+    }
+}
+
 pub struct Batch<'a, G> {
     pub name: &'a str,
     getter: &'a G,
-    count: usize,
+    direction: Direction,
 }
 
 impl<G> Fetcher<G> {
     pub fn fetch(&self, count: usize) -> Batch<G> {
+        self.fetch_in(Direction::Forward(count as u64))
+    }
+
+    pub fn fetch_in(&self, direction: Direction) -> Batch<G> {
         Batch{
             name: self.name.as_ref(),
             getter: &self.getter,
-            count,
+            direction,
         }
     }
+
+    pub fn moving(&self, direction: Direction) -> MoveCursor {
+        MoveCursor { name: self.name.as_ref(), direction }
+    }
+
+    pub fn close(&self) -> CloseCursor {
+        CloseCursor { name: self.name.as_ref() }
+    }
 }
 
 
@@ -219,7 +343,7 @@ impl<'a, G> IntoSql for Batch<'a, G> {
 
     fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
         buf.push_str("FETCH ");
-        buf.push_str(&self.count.to_string());
+        self.direction.push_sql(buf);
         buf.push_str(" FROM ");
         buf.push_str(self.name);
         idx
@@ -229,3 +353,116 @@ impl<'a, G> IntoSql for Batch<'a, G> {
         (self.getter, Unit)
     }
 }
+
+pub struct MoveCursor<'a> {
+    pub name: &'a str,
+    direction: Direction,
+}
+
+impl<'a> IntoSql for MoveCursor<'a> {
+    type Set = Unit;
+    type Get = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("MOVE ");
+        self.direction.push_sql(buf);
+        buf.push_str(" FROM ");
+        buf.push_str(self.name);
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+pub struct CloseCursor<'a> {
+    pub name: &'a str,
+}
+
+impl<'a> IntoSql for CloseCursor<'a> {
+    type Set = Unit;
+    type Get = Unit;
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("CLOSE ");
+        buf.push_str(self.name);
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (Unit, Unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+            (Name, NAME, "name", String),
+        });
+    }
+
+    mod posts {
+        table!(Posts, "posts" => ALL {
+            (UserId, USER_ID, "user_id", i32),
+            (Title, TITLE, "title", String),
+        });
+    }
+
+    mod latest {
+        table!(Latest, "latest" => ALL {
+            (Title, TITLE, "title", String),
+        });
+    }
+
+    use self::{users::Users, posts::Posts, latest::Latest};
+
+    fn render<Q: IntoSql>(query: Q) -> String {
+        let mut sql = String::new();
+        query.push_sql(&mut sql, 1);
+        sql
+    }
+
+    #[test]
+    fn renders_order_by() {
+        assert_eq!(render(Users.select().selecting(users::ALL)),
+                   "SELECT users.id, users.name FROM users");
+        assert_eq!(
+            render(Users.select().selecting(users::ALL)
+                   .filter(users::ID.equality::<Users>())
+                   .ordering(seq!(users::NAME.asc(), users::ID.desc()))),
+            "SELECT users.id, users.name FROM users WHERE id = $1 ORDER BY name ASC, id DESC");
+    }
+
+    #[test]
+    fn renders_function_sources() {
+        let ids = Users.calling("unnest({}::int4[])", param::<Vec<i32>>());
+        assert_eq!(render(ids.select().selecting(users::ID).filter(users::ID.equality::<Users>())),
+                   "SELECT users.id FROM unnest($1::int4[]) AS users WHERE id = $2");
+
+        let ids = Users.calling("generate_series({}, {})", param::<i32>());
+        match ids.select().selecting(users::ID).check() {
+            Err(Error::PlaceholderCount { expected: 1, found: 2, .. }) => {},
+            other => panic!("expected a placeholder count error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn renders_lateral_sources() {
+        let latest = Posts.select()
+            .selecting(posts::TITLE)
+            .filter(posts::USER_ID.equals_column(users::ID.of(Users))
+                    .and(posts::TITLE.equality::<Posts>()));
+        let query = Users.lateral(Latest, latest).select()
+            .selecting(seq!(users::NAME, latest::TITLE.of(Latest)))
+            .filter(users::ID.equality::<Users>());
+        assert_eq!(render(query),
+                   "SELECT users.name, latest.title FROM users, LATERAL (\
+                    SELECT posts.title FROM posts WHERE (user_id = users.id) AND (title = $1)\
+                    ) AS latest WHERE users.id = $2");
+    }
+}
@@ -11,20 +11,29 @@ builder! {
 }
 
 pub trait UpdValue<F: Source> {
+    type Set;
+    fn check(&self) -> Result<(), Error>;
     fn push_values(&self, buf: &mut String, idx: usize) -> usize;
+    fn into_types(self) -> Self::Set;
 }
 
 impl<F: Source, S: ColumnsSetter<F>> UpdValue<F> for Wrap<S> {
+    type Set = Wrap<S::Set>;
+
+    fn check(&self) -> Result<(), Error> {
+        if !self.0.push_assignments(&mut String::new(), 1).1 {
+            return Err(Error::EmptySelection);
+        }
+        self.0.check()
+    }
+
     fn push_values(&self, buf: &mut String, idx: usize) -> usize {
         buf.push_str(" SET ");
-        buf.push_str("(");
-        if !self.0.push_selection(buf) {
-            panic!("selection empty");
-        }
-        buf.push_str(") = ( ROW (");
-        let (idx, _) = self.0.push_values(buf, idx);
-        buf.push_str("))");
-        idx
+        self.0.push_assignments(buf, idx).0
+    }
+
+    fn into_types(self) -> Self::Set {
+        Wrap(self.0.into_types())
     }
 }
 
@@ -32,9 +41,13 @@ impl<F: Source, S: ColumnsSetter<F>> UpdValue<F> for Wrap<S> {
 impl<F: Source, V: UpdValue<F>, S: ReturningClause<F>, W: WhereClause<F>> IntoSql
 for UpdateBuilder<F, V, S, W> {
 
-    type Set = SqlInput<V, W::Set, Unit, Unit>;
+    type Set = SqlInput<V::Set, W::Set, Unit, Unit>;
     type Get = S;
 
+    fn check(&self) -> Result<(), Error> {
+        self.values.check()
+    }
+
     fn push_sql(&self, buf: &mut String, idx: usize) ->  usize {
         buf.push_str("UPDATE ");
         self.source.push_source(buf);
@@ -48,7 +61,7 @@ for UpdateBuilder<F, V, S, W> {
         (
             self.selection,
             SqlInput {
-                values: self.values,
+                values: self.values.into_types(),
                 where_clause: self.where_clause.into_types(),
                 limit: Unit,
                 offset: Unit,
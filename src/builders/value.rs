@@ -33,4 +33,12 @@ impl<
         self.limit.push_values(values.limit, buf);
         self.offset.push_values(values.offset, buf);
     }
+
+    #[inline]
+    fn check_values(&self, values: &ValueBuilder<V, W, L, O>) -> Result<(), Error> {
+        self.values.check_values(&values.values)?;
+        self.where_clause.check_values(&values.where_clause)?;
+        self.limit.check_values(&values.limit)?;
+        self.offset.check_values(&values.offset)
+    }
 }
@@ -0,0 +1,273 @@
+use std::{
+    cell::RefCell, collections::HashMap, fmt,
+    rc::{Rc, Weak}, sync::{Arc, Mutex},
+};
+use crate::*;
+use crate::query::{
+    synchronous::{Connection, Statement}, asynchronous::Prepared, TransactionError,
+};
+use futures03::{Stream, TryStreamExt, compat::Future01CompatExt};
+use postgres::stmt::Statement as PgStatement;
+
+// Prepared statements keyed by their rendered sql, so hot queries reuse
+// the server side statement.  The least recently used one is dropped
+// when full.  A statement whose cached plan went stale after a schema
+// change ("cached plan must not change result type") is dropped when
+// the server reports it, and is prepared afresh the next time.
+
+pub struct Lru<V> {
+    entries: HashMap<String, (V, u64)>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<V: Clone> Lru<V> {
+    pub fn new(capacity: usize) -> Self {
+        Lru { entries: HashMap::new(), tick: 0, capacity }
+    }
+
+    pub fn get(&mut self, sql: &str) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(sql).map(|entry| {
+            entry.1 = tick;
+            entry.0.clone()
+        })
+    }
+
+    pub fn insert(&mut self, sql: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&sql) {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.1)
+                .map(|(sql, _)| sql.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(sql, (value, self.tick));
+    }
+
+    pub fn remove(&mut self, sql: &str) {
+        self.entries.remove(sql);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// A stale plan is reported as feature_not_supported, a state shared with
+// much else; its message tells it apart.
+fn is_stale_plan<E: TransactionError + fmt::Display>(err: &E) -> bool {
+    err.sql_state() == Some("0A000")
+        && err.to_string().contains("cached plan must not change result type")
+}
+
+fn render<Q: IntoSql>(query: Q) -> Result<(String, usize, Q::Get, Q::Set), Error> {
+    query.check()?;
+    let mut sql: String = String::with_capacity(0x1000);
+    let idx = query.push_sql(&mut sql, 1);
+    let (getter, setter) = query.into_types();
+    Ok((sql, idx - 1, getter, setter))
+}
+
+type SyncLru<'conn> = RefCell<Lru<Rc<PgStatement<'conn>>>>;
+
+// The link from a statement back to the cache it came from.
+pub(crate) struct Cached<'conn> {
+    lru: Weak<SyncLru<'conn>>,
+    sql: String,
+}
+
+impl<'conn> Cached<'conn> {
+    pub(crate) fn check(&self, err: &postgres::Error) {
+        if !is_stale_plan(err) {
+            return;
+        }
+        if let Some(lru) = self.lru.upgrade() {
+            lru.borrow_mut().remove(&self.sql);
+        }
+    }
+}
+
+pub struct StatementCache<'conn> {
+    conn: &'conn dyn Connection,
+    lru: Rc<SyncLru<'conn>>,
+}
+
+impl<'conn> StatementCache<'conn> {
+    pub fn new(conn: &'conn dyn Connection, capacity: usize) -> Self {
+        StatementCache { conn, lru: Rc::new(RefCell::new(Lru::new(capacity))) }
+    }
+
+    pub fn prepare<Q: IntoSql>(&self, query: Q)
+    -> Result<Statement<'conn, Q::Get, Q::Set>, Error> {
+        let (sql, setter_count, getter, setter) = render(query)?;
+        let cached = self.lru.borrow_mut().get(&sql);
+        let statement = match cached {
+            Some(statement) => statement,
+            None => {
+                let statement = Rc::new(self.conn.prepare(&sql)?);
+                self.lru.borrow_mut().insert(sql.clone(), statement.clone());
+                statement
+            },
+        };
+        let cached = Cached { lru: Rc::downgrade(&self.lru), sql };
+        Ok(Statement::from_cache(statement, getter, setter, setter_count, cached))
+    }
+
+    pub fn clear(&self) {
+        self.lru.borrow_mut().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lru.borrow().is_empty()
+    }
+}
+
+type AsyncLru = Arc<Mutex<Lru<tokio_postgres::Statement>>>;
+
+// Async statements don't know their sql, so a stale plan clears the
+// whole cache; it follows a schema change anyway.
+fn check_async(lru: &AsyncLru, err: &Error) {
+    if let Error::Async(ref err) = *err {
+        if is_stale_plan(err) {
+            lru.lock().unwrap().clear();
+        }
+    }
+}
+
+// A client with its statement cache.
+pub struct CachedClient {
+    client: tokio_postgres::Client,
+    lru: AsyncLru,
+}
+
+impl CachedClient {
+    pub fn new(client: tokio_postgres::Client, capacity: usize) -> Self {
+        CachedClient { client, lru: Arc::new(Mutex::new(Lru::new(capacity))) }
+    }
+
+    pub fn client(&mut self) -> &mut tokio_postgres::Client {
+        &mut self.client
+    }
+
+    pub fn into_inner(self) -> tokio_postgres::Client {
+        self.client
+    }
+
+    pub fn clear(&self) {
+        self.lru.lock().unwrap().clear();
+    }
+
+    pub async fn prepare<Q: IntoSql>(&mut self, query: Q)
+    -> Result<Prepared<Q::Get, Q::Set>, Error> {
+        let (sql, setter_count, getter, setter) = render(query)?;
+        let cached = self.lru.lock().unwrap().get(&sql);
+        let statement = match cached {
+            Some(statement) => statement,
+            None => {
+                let statement = self.client.prepare(&sql).compat().await?;
+                self.lru.lock().unwrap().insert(sql, statement.clone());
+                statement
+            },
+        };
+        Ok(Prepared { statement, getter, setter, setter_count })
+    }
+
+    pub async fn execute_with<'a, Get, Set, A>(&mut self, stmt: &'a Prepared<Get, Set>, assignment: A)
+    -> Result<u64, Error>
+    where Set: Takes<'a, A> {
+        let result = stmt.execute_with(&mut self.client, assignment).await;
+        if let Err(ref e) = result {
+            check_async(&self.lru, e);
+        }
+        result
+    }
+
+    pub fn query_with<'a, Get, Set, A>(&mut self, stmt: &'a Prepared<Get, Set>, assignment: A)
+    -> impl Stream<Item = Result<TypedRow<'a, Get, tokio_postgres::Row>, Error>> + 'a
+    where Set: Takes<'a, A> {
+        let lru = self.lru.clone();
+        stmt.query_with(&mut self.client, assignment)
+            .inspect_err(move |e| check_async(&lru, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.insert("a".to_string(), 1);
+        lru.insert("b".to_string(), 2);
+        assert_eq!(lru.get("a"), Some(1));
+        lru.insert("c".to_string(), 3);
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("a"), Some(1));
+        assert_eq!(lru.get("c"), Some(3));
+
+        lru.insert("d".to_string(), 4);
+        assert_eq!(lru.get("a"), None);
+        assert_eq!(lru.get("c"), Some(3));
+    }
+
+    #[test]
+    fn lru_replaces_without_evicting() {
+        let mut lru = Lru::new(2);
+        lru.insert("a".to_string(), 1);
+        lru.insert("b".to_string(), 2);
+        lru.insert("a".to_string(), 3);
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get("a"), Some(3));
+        assert_eq!(lru.get("b"), Some(2));
+    }
+
+    #[test]
+    fn lru_without_capacity_keeps_nothing() {
+        let mut lru = Lru::new(0);
+        lru.insert("a".to_string(), 1);
+        assert!(lru.is_empty());
+        assert_eq!(lru.get("a"), None);
+    }
+
+    struct Reported(&'static str, &'static str);
+
+    impl TransactionError for Reported {
+        fn sql_state(&self) -> Option<&str> {
+            Some(self.0)
+        }
+    }
+
+    impl fmt::Display for Reported {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "db error: ERROR: {}", self.1)
+        }
+    }
+
+    #[test]
+    fn stale_plan_needs_its_message() {
+        assert!(is_stale_plan(&Reported("0A000", "cached plan must not change result type")));
+        assert!(!is_stale_plan(&Reported("0A000", "LOCK TABLE is not supported")));
+        assert!(!is_stale_plan(&Reported("42P01", "cached plan must not change result type")));
+    }
+}
@@ -0,0 +1,603 @@
+use std::{cmp, future::Future, io::{self, Read, Write}, sync::Arc};
+use crate::{*, utils::*};
+use crate::query::synchronous::Connection;
+use futures03::{
+    Stream, TryFutureExt, TryStreamExt, stream,
+    compat::{Future01CompatExt, Stream01CompatExt},
+};
+use postgres::{
+    stmt::Statement as PgStatement,
+    types::{IsNull, ToSql, Type},
+};
+
+// `COPY .. FROM STDIN` in the binary format.  Each row is pushed through
+// the setter (as `Takes`), and encoded as the types of the columns,
+// which are looked up with a `SELECT .. LIMIT 0` when preparing.  The
+// rows are encoded lazily, a chunk at a time, as the server takes them.
+//
+// `COPY (SELECT ..) TO STDOUT` either hands the output to a writer as is
+// (in any `Format`), or, copied out as `Binary`, decodes it into
+// `RawRow`s, which the getter of the query reads like any other row;
+// `TypedRow::try_as_value` reads them without panicking on a column the
+// getter doesn't fit.
+
+const CHUNK: usize = 0x10000;
+
+// signature, flags and header extension length
+const HEADER: &[u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+
+pub struct Encoder<'a, S, I> {
+    setter: &'a S,
+    types: &'a [Type],
+    rows: I,
+    started: bool,
+    done: bool,
+}
+
+impl<'a, S: Takes<'a, I::Item>, I: Iterator> Encoder<'a, S, I> {
+    pub fn new(setter: &'a S, types: &'a [Type], rows: I) -> Self {
+        Encoder { setter, types, rows, started: false, done: false }
+    }
+
+    fn encode(&self, row: I::Item, buf: &mut Vec<u8>) -> io::Result<()> {
+        let mut values: Vec<&'a ToSql> = Vec::with_capacity(self.types.len());
+        self.setter.push_values(row, &mut values);
+        if values.len() != self.types.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "row has {} values for {} columns", values.len(), self.types.len())));
+        }
+
+        buf.extend_from_slice(&(values.len() as i16).to_be_bytes());
+        for (value, ty) in values.iter().zip(self.types) {
+            let at = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            let len = match value.to_sql_checked(ty, buf) {
+                Ok(IsNull::Yes) => -1,
+                Ok(IsNull::No) => (buf.len() - at - 4) as i32,
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+            };
+            buf[at..at + 4].copy_from_slice(&len.to_be_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S: Takes<'a, I::Item>, I: Iterator> Iterator for Encoder<'a, S, I> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut buf = Vec::with_capacity(CHUNK);
+        if !self.started {
+            buf.extend_from_slice(HEADER);
+            self.started = true;
+        }
+        while buf.len() < CHUNK {
+            match self.rows.next() {
+                Some(row) => if let Err(e) = self.encode(row, &mut buf) {
+                    self.done = true;
+                    return Some(Err(e));
+                },
+                None => {
+                    buf.extend_from_slice(&(-1i16).to_be_bytes());
+                    self.done = true;
+                    break;
+                },
+            }
+        }
+        Some(Ok(buf))
+    }
+}
+
+struct ChunkReader<E> {
+    chunks: E,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl<E: Iterator<Item = io::Result<Vec<u8>>>> Read for ChunkReader<E> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                },
+                None => return Ok(0),
+            }
+        }
+        let len = cmp::min(out.len(), self.chunk.len() - self.pos);
+        out[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+pub struct CopyStatement<'conn, Set> {
+    pub statement: PgStatement<'conn>,
+    pub types: Vec<Type>,
+    pub setter: Set,
+}
+
+impl<'conn, Set> CopyStatement<'conn, Set> {
+    // Copies the rows in; returns how many.
+    pub fn copy_in<'a, I: IntoIterator>(&'a self, rows: I) -> Result<u64, Error>
+    where Set: Takes<'a, I::Item> {
+        let mut reader = ChunkReader {
+            chunks: Encoder::new(&self.setter, &self.types, rows.into_iter()),
+            chunk: vec![],
+            pos: 0,
+        };
+        Ok(self.statement.copy_in(&[], &mut reader)?)
+    }
+}
+
+pub struct CopyPrepared<Set> {
+    pub statement: tokio_postgres::Statement,
+    pub types: Vec<Type>,
+    pub setter: Set,
+}
+
+impl<Set> CopyPrepared<Set> {
+    pub fn copy_in<'a, I>(&'a self, cl: &mut tokio_postgres::Client, rows: I)
+    -> impl Future<Output = Result<u64, Error>> + 'a
+    where I: IntoIterator, I::IntoIter: 'a, Set: Takes<'a, I::Item> {
+        let chunks = Encoder::new(&self.setter, &self.types, rows.into_iter());
+        cl.copy_in(&self.statement, &[], futures::stream::iter_result(chunks))
+            .compat()
+            .map_err(Error::from)
+    }
+}
+
+impl<F: Source, V: ColumnsSetter<F>> CopyInBuilder<F, Wrap<V>> {
+    pub fn prepare_copy<'conn>(self, conn: &'conn dyn Connection)
+    -> Result<CopyStatement<'conn, V::Set>, Error> {
+        self.check()?;
+        let mut types_sql = String::new();
+        self.push_types_sql(&mut types_sql);
+        let types = conn.prepare(&types_sql)?.columns().iter()
+            .map(|c| c.type_().clone())
+            .collect();
+
+        let mut sql = String::new();
+        self.push_sql(&mut sql, 1);
+        let (_, setter) = self.into_types();
+        Ok(CopyStatement { statement: conn.prepare(&sql)?, types, setter })
+    }
+
+    pub async fn prepare_copy_async(self, cl: &mut tokio_postgres::Client)
+    -> Result<CopyPrepared<V::Set>, Error> {
+        self.check()?;
+        let mut types_sql = String::new();
+        self.push_types_sql(&mut types_sql);
+        let types = cl.prepare(&types_sql).compat().await?.columns().iter()
+            .map(|c| c.type_().clone())
+            .collect();
+
+        let mut sql = String::new();
+        self.push_sql(&mut sql, 1);
+        let (_, setter) = self.into_types();
+        let statement = cl.prepare(&sql).compat().await?;
+        Ok(CopyPrepared { statement, types, setter })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    // csv, with a header line naming the columns
+    CsvHeader,
+    Binary,
+}
+
+// The binary format, decoded into rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binary;
+
+pub trait CopyFormat: Copy {
+    fn options(self) -> &'static str;
+
+    // Whether the rows are decoded, and so need the column types.
+    #[inline]
+    fn decoded(self) -> bool {
+        false
+    }
+}
+
+impl CopyFormat for Format {
+    fn options(self) -> &'static str {
+        match self {
+            Format::Text => "",
+            Format::Csv => " (FORMAT csv)",
+            Format::CsvHeader => " (FORMAT csv, HEADER)",
+            Format::Binary => " (FORMAT binary)",
+        }
+    }
+}
+
+impl CopyFormat for Binary {
+    fn options(self) -> &'static str {
+        Format::Binary.options()
+    }
+
+    #[inline]
+    fn decoded(self) -> bool {
+        true
+    }
+}
+
+pub struct CopyOut<Q, Fmt> {
+    pub query: Q,
+    pub format: Fmt,
+}
+
+// `COPY` can't take parameters, so neither can the query.
+impl<G, Q: IntoSql<Get = Wrap<G>>, Fmt: CopyFormat> IntoSql for CopyOut<Q, Fmt> {
+    type Get = Q::Get;
+    type Set = Unit;
+
+    fn check(&self) -> Result<(), Error> {
+        self.query.check()?;
+        let found = self.query.push_sql(&mut String::new(), 1) - 1;
+        if found != 0 {
+            return Err(Error::ParameterCount { expected: 0, found });
+        }
+        Ok(())
+    }
+
+    fn push_sql(&self, buf: &mut String, idx: usize) -> usize {
+        buf.push_str("COPY (");
+        self.query.push_sql(buf, idx);
+        buf.push_str(") TO STDOUT");
+        buf.push_str(self.format.options());
+        idx
+    }
+
+    fn into_types(self) -> (Self::Get, Self::Set) {
+        (self.query.into_types().0, Unit)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Decodes the binary format as it arrives, in chunks of any size.
+pub struct Decoder {
+    types: Arc<Vec<Type>>,
+    buf: Vec<u8>,
+    header: bool,
+    done: bool,
+}
+
+impl Decoder {
+    pub fn new(types: Arc<Vec<Type>>) -> Self {
+        Decoder { types, buf: vec![], header: false, done: false }
+    }
+
+    // The rows completed by `chunk`.
+    pub fn feed(&mut self, chunk: &[u8]) -> io::Result<Vec<RawRow>> {
+        if self.done {
+            return if chunk.is_empty() { Ok(vec![]) } else { Err(invalid("data after trailer")) };
+        }
+        self.buf.extend_from_slice(chunk);
+
+        let mut pos = 0;
+        if !self.header {
+            if self.buf.len() < HEADER.len() {
+                return Ok(vec![]);
+            }
+            if self.buf[..11] != HEADER[..11] {
+                return Err(invalid("bad COPY signature"));
+            }
+            let ext = be_i32(&self.buf[15..19]);
+            if ext < 0 {
+                return Err(invalid("bad COPY header"));
+            }
+            if self.buf.len() < HEADER.len() + ext as usize {
+                return Ok(vec![]);
+            }
+            pos = HEADER.len() + ext as usize;
+            self.header = true;
+        }
+
+        let mut rows = vec![];
+        while let Some((row, end)) = self.row(pos)? {
+            pos = end;
+            match row {
+                Some(row) => rows.push(row),
+                None => {
+                    self.done = true;
+                    break;
+                },
+            }
+        }
+        self.buf.drain(..pos);
+        if self.done && !self.buf.is_empty() {
+            return Err(invalid("data after trailer"));
+        }
+        Ok(rows)
+    }
+
+    // The row (or trailer, as `None`) at `pos` and where it ends, if it
+    // has arrived whole.
+    fn row(&self, pos: usize) -> io::Result<Option<(Option<RawRow>, usize)>> {
+        let buf = &self.buf[pos..];
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let count = i16::from_be_bytes([buf[0], buf[1]]);
+        if count == -1 {
+            return Ok(Some((None, pos + 2)));
+        }
+        if count as usize != self.types.len() {
+            return Err(invalid("unexpected number of columns"));
+        }
+
+        let mut at = 2;
+        let mut fields = Vec::with_capacity(self.types.len());
+        for _ in 0..count {
+            if buf.len() < at + 4 {
+                return Ok(None);
+            }
+            let len = be_i32(&buf[at..at + 4]);
+            at += 4;
+            if len < 0 {
+                fields.push(None);
+                continue;
+            }
+            if buf.len() < at + len as usize {
+                return Ok(None);
+            }
+            fields.push(Some((at, at + len as usize)));
+            at += len as usize;
+        }
+        let row = RawRow::new(self.types.clone(), buf[..at].to_vec(), fields);
+        Ok(Some((Some(row), pos + at)))
+    }
+}
+
+fn be_i32(bytes: &[u8]) -> i32 {
+    i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// Feeds what the server writes to a decoder, handing the rows to `f`;
+// keeps the error of data that doesn't decode.
+struct RowWriter<F> {
+    decoder: Decoder,
+    f: F,
+    error: Option<io::Error>,
+}
+
+impl<F: FnMut(RawRow)> Write for RowWriter<F> {
+    fn write(&mut self, chunk: &[u8]) -> io::Result<usize> {
+        match self.decoder.feed(chunk) {
+            Ok(rows) => rows.into_iter().for_each(&mut self.f),
+            Err(e) => {
+                self.error = Some(e);
+                return Err(invalid("malformed COPY data"));
+            },
+        }
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct CopyOutStatement<'conn, Get, Fmt> {
+    pub statement: PgStatement<'conn>,
+    pub types: Arc<Vec<Type>>,
+    pub getter: Get,
+    pub format: Fmt,
+}
+
+impl<'conn, Get, Fmt> CopyOutStatement<'conn, Get, Fmt> {
+    // Writes the output as the server sends it; returns the row count.
+    pub fn to_writer<W: Write>(&self, out: &mut W) -> Result<u64, Error> {
+        Ok(self.statement.copy_out(&[], out)?)
+    }
+}
+
+impl<'conn, Get> CopyOutStatement<'conn, Get, Binary> {
+    // Decodes each row; returns the row count.
+    pub fn for_each<'a, F>(&'a self, mut each: F) -> Result<u64, Error>
+    where F: FnMut(TypedRow<'a, Get, RawRow>) {
+        let getter = &self.getter;
+        let mut writer = RowWriter {
+            decoder: Decoder::new(self.types.clone()),
+            f: |row: RawRow| each(TypedRow(getter, row)),
+            error: None,
+        };
+        let result = self.statement.copy_out(&[], &mut writer);
+        match writer.error {
+            Some(e) => Err(Error::Copy(e)),
+            None => Ok(result?),
+        }
+    }
+}
+
+pub struct CopyOutPrepared<Get, Fmt> {
+    pub statement: tokio_postgres::Statement,
+    pub types: Arc<Vec<Type>>,
+    pub getter: Get,
+    pub format: Fmt,
+}
+
+impl<Get, Fmt> CopyOutPrepared<Get, Fmt> {
+    // The output, in chunks as the server sends them.
+    pub fn raw(&self, cl: &mut tokio_postgres::Client)
+    -> impl Stream<Item = Result<Vec<u8>, Error>> {
+        cl.copy_out(&self.statement, &[])
+            .compat()
+            .map_err(Error::from)
+            .map_ok(|chunk| chunk.to_vec())
+    }
+}
+
+impl<Get> CopyOutPrepared<Get, Binary> {
+    // Decodes each row.
+    pub fn rows<'a>(&'a self, cl: &mut tokio_postgres::Client)
+    -> impl Stream<Item = Result<TypedRow<'a, Get, RawRow>, Error>> + 'a {
+        let getter = &self.getter;
+        let mut decoder = Decoder::new(self.types.clone());
+        cl.copy_out(&self.statement, &[])
+            .compat()
+            .map_err(Error::from)
+            .map_ok(move |chunk| stream::iter(match decoder.feed(&chunk) {
+                Ok(rows) => rows.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(Error::Copy(e))],
+            }))
+            .try_flatten()
+            .map_ok(move |row| TypedRow(getter, row))
+    }
+}
+
+impl<G, Q: IntoSql<Get = Wrap<G>>, Fmt: CopyFormat> CopyOut<Q, Fmt> {
+    pub fn prepare_copy_out<'conn>(self, conn: &'conn dyn Connection)
+    -> Result<CopyOutStatement<'conn, Q::Get, Fmt>, Error> {
+        self.check()?;
+        // the column types are needed to decode the binary format
+        let types = if self.format.decoded() {
+            let mut query_sql = String::new();
+            self.query.push_sql(&mut query_sql, 1);
+            conn.prepare(&query_sql)?.columns().iter().map(|c| c.type_().clone()).collect()
+        } else {
+            vec![]
+        };
+
+        let mut sql = String::new();
+        self.push_sql(&mut sql, 1);
+        let format = self.format;
+        let (getter, _) = self.into_types();
+        Ok(CopyOutStatement {
+            statement: conn.prepare(&sql)?,
+            types: Arc::new(types),
+            getter, format,
+        })
+    }
+
+    pub async fn prepare_copy_out_async(self, cl: &mut tokio_postgres::Client)
+    -> Result<CopyOutPrepared<Q::Get, Fmt>, Error> {
+        self.check()?;
+        let types = if self.format.decoded() {
+            let mut query_sql = String::new();
+            self.query.push_sql(&mut query_sql, 1);
+            cl.prepare(&query_sql).compat().await?.columns().iter()
+                .map(|c| c.type_().clone())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let mut sql = String::new();
+        self.push_sql(&mut sql, 1);
+        let format = self.format;
+        let (getter, _) = self.into_types();
+        let statement = cl.prepare(&sql).compat().await?;
+        Ok(CopyOutPrepared { statement, types: Arc::new(types), getter, format })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int4() -> Vec<Type> {
+        vec![Type::from_oid(23).unwrap()]
+    }
+
+    fn encode(values: &[Option<i32>]) -> Vec<u8> {
+        let (setter, types) = (param::<Option<i32>>(), int4());
+        Encoder::new(&setter, &types, values.iter())
+            .collect::<io::Result<Vec<_>>>().unwrap()
+            .concat()
+    }
+
+    #[test]
+    fn encoder_frames_rows() {
+        let mut expected = HEADER.to_vec();
+        expected.extend_from_slice(b"\0\x01\0\0\0\x04\0\0\0\x07");
+        expected.extend_from_slice(b"\0\x01\xff\xff\xff\xff");
+        expected.extend_from_slice(b"\xff\xff");
+        assert_eq!(encode(&[Some(7), None]), expected);
+        assert_eq!(&HEADER[..11], b"PGCOPY\n\xff\r\n\0");
+    }
+
+    #[test]
+    fn encoder_frames_no_rows() {
+        let mut expected = HEADER.to_vec();
+        expected.extend_from_slice(b"\xff\xff");
+        assert_eq!(encode(&[]), expected);
+    }
+
+    #[test]
+    fn encoder_rejects_wrong_value_count() {
+        let (setter, types) = (seq!(param::<i32>(), param::<i32>()), int4());
+        let rows = vec![Seq(1, 2)];
+        let mut chunks = Encoder::new(&setter, &types, rows.iter());
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    fn values(rows: &[RawRow]) -> Vec<Option<i32>> {
+        rows.iter().map(|row| row.try_get(0).unwrap()).collect()
+    }
+
+    #[test]
+    fn decoder_reads_rows_across_chunks() {
+        let data = encode(&[Some(1), None, Some(-3)]);
+        for size in 1..data.len() {
+            let mut decoder = Decoder::new(Arc::new(int4()));
+            let mut rows = vec![];
+            for chunk in data.chunks(size) {
+                rows.extend(decoder.feed(chunk).unwrap());
+            }
+            assert_eq!(values(&rows), vec![Some(1), None, Some(-3)], "chunks of {}", size);
+        }
+    }
+
+    #[test]
+    fn decoder_skips_header_extension() {
+        let mut data = HEADER[..15].to_vec();
+        data.extend_from_slice(b"\0\0\0\x02ab");
+        data.extend_from_slice(&encode(&[Some(5)])[HEADER.len()..]);
+        let rows = Decoder::new(Arc::new(int4())).feed(&data).unwrap();
+        assert_eq!(values(&rows), vec![Some(5)]);
+    }
+
+    #[test]
+    fn decoder_rejects_malformed_data() {
+        let types = Arc::new(int4());
+        assert!(Decoder::new(types.clone()).feed(b"PGCOPY\n\xff\r\n\x01\0\0\0\0\0\0\0\0").is_err());
+
+        let mut data = HEADER.to_vec();
+        data.extend_from_slice(b"\0\x02");
+        assert!(Decoder::new(types.clone()).feed(&data).is_err());
+
+        let mut data = encode(&[Some(1)]);
+        data.push(0);
+        assert!(Decoder::new(types.clone()).feed(&data).is_err());
+
+        let mut decoder = Decoder::new(types);
+        decoder.feed(&encode(&[])).unwrap();
+        assert!(decoder.feed(b"\0").is_err());
+    }
+
+    #[test]
+    fn raw_rows_report_bad_columns() {
+        let rows = Decoder::new(Arc::new(int4())).feed(&encode(&[Some(1), None])).unwrap();
+        assert_eq!(rows[0].try_get::<i32>(0).unwrap(), 1);
+        match rows[0].try_get::<String>(0) {
+            Err(Error::Decode { column: 0, .. }) => {},
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+        assert!(rows[0].try_get::<i32>(1).is_err());
+        assert!(rows[1].try_get::<i32>(0).is_err());
+        assert_eq!(rows[1].try_get::<Option<i32>>(0).unwrap(), None);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{collections::VecDeque, sync::Arc};
+use crate::{*, utils::*, error::check_count};
+use crate::query::synchronous::{Connection, Transaction};
+use crate::query::asynchronous::{self, send};
+use futures03::{Stream, TryStreamExt, stream, compat::{Future01CompatExt, Stream01CompatExt}};
+use postgres::types::Type;
+
+// Cursors that fetch themselves, a batch at a time.  Unless declared
+// `WITH HOLD`, the cursor is declared in a transaction of its own (a
+// savepoint, if the connection is already a transaction), which ends
+// along with the cursor.  Dropping a cursor that's still open closes it.
+
+// What a batch of `rows` leaves of the cursor.  A short batch is no end:
+// a `SCROLL` cursor can be moved back and fetched again, so only an empty
+// batch runs it out, and it stays open (until closed or dropped) when it
+// does; other cursors close as they run out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fetched {
+    More,
+    RunOut,
+    Closing,
+}
+
+fn after_batch(rows: usize, options: &CursorOptions) -> Fetched {
+    match (rows, options.is_scroll()) {
+        (0, true) => Fetched::RunOut,
+        (0, false) => Fetched::Closing,
+        _ => Fetched::More,
+    }
+}
+
+// The rows of `postgres` borrow their batch; these are copied out, so
+// the iterator can hand them over one at a time (see `RawRow::try_get`
+// and `TypedRow::try_as_value` to read them without panicking).
+fn raw_row(types: &Arc<Vec<Type>>, row: postgres::rows::Row) -> RawRow {
+    let mut data = vec![];
+    let fields = (0..row.len()).map(|idx| {
+        row.get_bytes(idx).map(|bytes| {
+            let start = data.len();
+            data.extend_from_slice(bytes);
+            (start, data.len())
+        })
+    }).collect();
+    RawRow::new(types.clone(), data, fields)
+}
+
+pub struct CursorIter<'a, 'conn, G> {
+    conn: &'conn dyn Connection,
+    tx: Option<Transaction<'conn>>,
+    fetcher: &'a Fetcher<G>,
+    fetch_sql: String,
+    types: Option<Arc<Vec<Type>>>,
+    rows: VecDeque<RawRow>,
+    fetched: Fetched,
+    closed: bool,
+    on_close_error: Option<Box<dyn FnMut(Error) + 'a>>,
+}
+
+impl<'a, 'conn, G> CursorIter<'a, 'conn, G> {
+    pub fn declare_with<S, A>(conn: &'conn dyn Connection, query: &'a CursorQuery<S>,
+                              fetcher: &'a Fetcher<G>, assignment: A) -> Result<Self, Error>
+    where S: Takes<'a, A> {
+        let mut values = vec![];
+        query.setter.push_values(assignment, &mut values);
+        check_count(query.idx - 1, values.len())?;
+
+        let tx = if fetcher.options.is_hold() { None } else { Some(conn.transaction()?) };
+        match tx {
+            Some(ref tx) => tx.execute(&query.prepared, &values[..])?,
+            None => conn.execute(&query.prepared, &values[..])?,
+        };
+
+        let mut fetch_sql = String::new();
+        fetcher.fetch(fetcher.options.batch_size()).push_sql(&mut fetch_sql, 1);
+        Ok(CursorIter {
+            conn, tx, fetcher, fetch_sql,
+            types: None,
+            rows: VecDeque::new(),
+            fetched: Fetched::More,
+            closed: false,
+            on_close_error: None,
+        })
+    }
+
+    pub fn declare<S>(conn: &'conn dyn Connection, query: &'a CursorQuery<S>,
+                      fetcher: &'a Fetcher<G>) -> Result<Self, Error>
+    where S: Takes<'a, Unit> {
+        Self::declare_with(conn, query, fetcher, Unit)
+    }
+
+    // Takes the error of closing the cursor as it drops, which can't be
+    // returned; without this, it's printed to stderr.
+    pub fn on_close_error<R: FnMut(Error) + 'a>(mut self, report: R) -> Self {
+        self.on_close_error = Some(Box::new(report));
+        self
+    }
+
+    fn conn(&self) -> &dyn Connection {
+        match self.tx {
+            Some(ref tx) => tx,
+            None => self.conn,
+        }
+    }
+
+    fn fetch(&mut self) -> Result<(), Error> {
+        let rows = self.conn().query(&self.fetch_sql, &[])?;
+        self.fetched = after_batch(rows.len(), &self.fetcher.options);
+        let types = self.types.get_or_insert_with(|| {
+            Arc::new(rows.columns().iter().map(|c| c.type_().clone()).collect())
+        });
+        self.rows.extend(rows.iter().map(|row| raw_row(types, row)));
+        Ok(())
+    }
+
+    // Moves the cursor (which must be `SCROLL` to move backwards);
+    // returns how many rows it moved over.  Rows fetched but not yet
+    // yielded are dropped, and the cursor moves from the last of them.
+    pub fn move_by(&mut self, direction: Direction) -> Result<u64, Error> {
+        self.rows.clear();
+        self.fetched = Fetched::More;
+        let mut sql = String::new();
+        self.fetcher.moving(direction).push_sql(&mut sql, 1);
+        Ok(self.conn().execute(&sql, &[])?)
+    }
+
+    // Closes the cursor, and ends its transaction.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let mut sql = String::new();
+        self.fetcher.close().push_sql(&mut sql, 1);
+        let closed = self.conn().batch_execute(&sql);
+        match self.tx.take() {
+            Some(tx) => {
+                closed?;
+                Ok(tx.commit()?)
+            },
+            None => Ok(closed?),
+        }
+    }
+}
+
+impl<'a, 'conn, G> Iterator for CursorIter<'a, 'conn, G> {
+    type Item = Result<TypedRow<'a, G, RawRow>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.closed {
+            return None;
+        }
+        if self.rows.is_empty() && self.fetched == Fetched::More {
+            if let Err(e) = self.fetch() {
+                let _ = self.finish();
+                return Some(Err(e));
+            }
+        }
+        match (self.rows.pop_front(), self.fetched) {
+            (Some(row), _) => Some(Ok(TypedRow(&self.fetcher.getter, row))),
+            (None, Fetched::Closing) => self.finish().err().map(Err),
+            (None, _) => None,
+        }
+    }
+}
+
+impl<'a, 'conn, G> Drop for CursorIter<'a, 'conn, G> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            match self.on_close_error {
+                Some(ref mut report) => report(e),
+                None => eprintln!("tygres: closing cursor {}: {}", self.fetcher.name, e),
+            }
+        }
+    }
+}
+
+enum Client<'c> {
+    Transaction(asynchronous::Transaction<'c>),
+    Client(&'c mut tokio_postgres::Client),
+}
+
+impl<'c> Client<'c> {
+    fn get(&mut self) -> &mut tokio_postgres::Client {
+        match *self {
+            Client::Transaction(ref mut tx) => tx.client(),
+            Client::Client(ref mut cl) => &mut **cl,
+        }
+    }
+}
+
+pub struct Cursor<'a, 'c, G> {
+    client: Option<Client<'c>>,
+    fetcher: &'a Fetcher<G>,
+    fetch: tokio_postgres::Statement,
+    fetched: Fetched,
+}
+
+impl<'a, 'c, G> Cursor<'a, 'c, G> {
+    pub async fn declare_with<S, A>(cl: &'c mut tokio_postgres::Client, query: &'a CursorQuery<S>,
+                                    fetcher: &'a Fetcher<G>, assignment: A)
+    -> Result<Cursor<'a, 'c, G>, Error>
+    where S: Takes<'a, A> {
+        let mut client = if fetcher.options.is_hold() {
+            Client::Client(cl)
+        } else {
+            Client::Transaction(asynchronous::Transaction::begin(cl).await?)
+        };
+
+        let declare = client.get().prepare(&query.prepared).compat().await?;
+        let executed = {
+            let mut values = vec![];
+            query.setter.push_values(assignment, &mut values);
+            check_count(query.idx - 1, values.len())
+                .map(|()| client.get().execute(&declare, &values[..]).compat())
+        };
+        executed?.await?;
+
+        let mut fetch_sql = String::new();
+        fetcher.fetch(fetcher.options.batch_size()).push_sql(&mut fetch_sql, 1);
+        let fetch = client.get().prepare(&fetch_sql).compat().await?;
+        Ok(Cursor { client: Some(client), fetcher, fetch, fetched: Fetched::More })
+    }
+
+    pub async fn declare<S>(cl: &'c mut tokio_postgres::Client, query: &'a CursorQuery<S>,
+                            fetcher: &'a Fetcher<G>)
+    -> Result<Cursor<'a, 'c, G>, Error>
+    where S: Takes<'a, Unit> {
+        Self::declare_with(cl, query, fetcher, Unit).await
+    }
+
+    // The next batch, or `None` once the cursor runs out (and, unless
+    // it's `SCROLL`, closes).
+    pub async fn next_batch(&mut self)
+    -> Result<Option<Vec<TypedRow<'a, G, tokio_postgres::Row>>>, Error> {
+        let client = match (&mut self.client, self.fetched) {
+            (Some(client), Fetched::More) => client.get(),
+            _ => return Ok(None),
+        };
+        let rows: Vec<_> = client.query(&self.fetch, &[]).compat().try_collect().await?;
+        self.fetched = after_batch(rows.len(), &self.fetcher.options);
+        match self.fetched {
+            Fetched::More => {},
+            Fetched::RunOut => return Ok(None),
+            Fetched::Closing => {
+                self.close_mut().await?;
+                return Ok(None);
+            },
+        }
+        let getter = &self.fetcher.getter;
+        Ok(Some(rows.into_iter().map(|row| TypedRow(getter, row)).collect()))
+    }
+
+    // Moves the cursor; returns how many rows it moved over.
+    pub async fn move_by(&mut self, direction: Direction) -> Result<u64, Error> {
+        self.fetched = Fetched::More;
+        let mut sql = String::new();
+        self.fetcher.moving(direction).push_sql(&mut sql, 1);
+        match self.client {
+            Some(ref mut client) => {
+                let cl = client.get();
+                let stmt = cl.prepare(&sql).compat().await?;
+                Ok(cl.execute(&stmt, &[]).compat().await?)
+            },
+            None => Ok(0),
+        }
+    }
+
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.close_mut().await
+    }
+
+    async fn close_mut(&mut self) -> Result<(), Error> {
+        let mut sql = String::new();
+        self.fetcher.close().push_sql(&mut sql, 1);
+        match self.client.take() {
+            Some(Client::Transaction(mut tx)) => {
+                tx.client().batch_execute(&sql).compat().await?;
+                Ok(tx.commit().await?)
+            },
+            Some(Client::Client(cl)) => Ok(cl.batch_execute(&sql).compat().await?),
+            None => Ok(()),
+        }
+    }
+
+    // The rows, one at a time.
+    pub fn into_stream(self)
+    -> impl Stream<Item = Result<TypedRow<'a, G, tokio_postgres::Row>, Error>>
+    + use<'a, 'c, G> {
+        stream::try_unfold(self, |mut cursor| async move {
+            let batch = cursor.next_batch().await?;
+            Ok::<_, Error>(batch.map(|rows| {
+                (stream::iter(rows.into_iter().map(Ok)), cursor)
+            }))
+        }).try_flatten()
+    }
+}
+
+// A transaction rolls back (closing the cursor) as it drops; a held
+// cursor is closed without waiting.
+impl<'a, 'c, G> Drop for Cursor<'a, 'c, G> {
+    fn drop(&mut self) {
+        if let Some(Client::Client(ref mut cl)) = self.client {
+            let mut sql = String::new();
+            self.fetcher.close().push_sql(&mut sql, 1);
+            send(cl.batch_execute(&sql));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+        });
+    }
+    use self::users::{Users, ID};
+
+    fn render<Q: IntoSql>(query: Q) -> String {
+        let mut sql = String::new();
+        query.push_sql(&mut sql, 1);
+        sql
+    }
+
+    #[test]
+    fn only_empty_batches_run_out() {
+        let (forward, scroll) = (CursorOptions::new().batch(2), CursorOptions::new().scroll(true));
+        assert_eq!(after_batch(2, &forward), Fetched::More);
+        // a short batch, as after moving back from the end
+        assert_eq!(after_batch(1, &forward), Fetched::More);
+        assert_eq!(after_batch(1, &scroll), Fetched::More);
+        assert_eq!(after_batch(0, &forward), Fetched::Closing);
+        assert_eq!(after_batch(0, &scroll), Fetched::RunOut);
+    }
+
+    #[test]
+    fn renders_cursor_statements() {
+        let options = CursorOptions::new().scroll(true).hold(true).batch(50);
+        let (query, fetcher) = Users.select().selecting(ID).into_cursor_with("c", options);
+        assert_eq!(render(query),
+                   "DECLARE c SCROLL CURSOR WITH HOLD FOR SELECT users.id FROM users");
+        assert_eq!(render(fetcher.fetch(fetcher.options.batch_size())),
+                   "FETCH FORWARD 50 FROM c");
+        assert_eq!(render(fetcher.moving(Direction::Backward(3))), "MOVE BACKWARD 3 FROM c");
+        assert_eq!(render(fetcher.moving(Direction::Absolute(-1))), "MOVE ABSOLUTE -1 FROM c");
+        assert_eq!(render(fetcher.close()), "CLOSE c");
+    }
+}
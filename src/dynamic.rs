@@ -0,0 +1,258 @@
+use crate::{*, utils::*, order_by::OrderSeq};
+use postgres::types::ToSql;
+
+// Type-erased counterparts of `Clause`, `Selection` and `OrderSeq`, to
+// assemble queries at runtime.  Erased clauses own their values (see
+// `Clause::taking`), so they can be collected without the static types.
+
+pub trait DynSetter {
+    fn push_dyn<'a>(&'a self, buf: &mut Vec<&'a ToSql>);
+}
+
+impl<S: for<'a> Takes<'a, Unit>> DynSetter for S {
+    #[inline]
+    fn push_dyn<'a>(&'a self, buf: &mut Vec<&'a ToSql>) {
+        self.push_values(Unit, buf);
+    }
+}
+
+pub struct BoxedSetter(Box<dyn DynSetter>);
+
+impl<'a> Takes<'a, Unit> for BoxedSetter {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: Unit, buf: &mut Vec<&'a ToSql>) {
+        self.0.push_dyn(buf);
+    }
+}
+
+impl<'a, S: Takes<'a, Unit>> Takes<'a, Unit> for Vec<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: Unit, buf: &mut Vec<&'a ToSql>) {
+        for setter in self {
+            setter.push_values(Unit, buf);
+        }
+    }
+}
+
+pub trait DynClause<F> {
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize;
+    fn is_empty(&self) -> bool;
+    fn into_setter(self: Box<Self>) -> BoxedSetter;
+}
+
+impl<F: Source, C: Clause<F>> DynClause<F> for C
+where C::Set: for<'a> Takes<'a, Unit> + 'static {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        Clause::push_clause(self, buf, idx)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        Clause::is_empty(self)
+    }
+
+    fn into_setter(self: Box<Self>) -> BoxedSetter {
+        BoxedSetter(Box::new((*self).into_types()))
+    }
+}
+
+pub struct BoxedClause<F>(Box<dyn DynClause<F>>);
+
+impl<F> BoxedClause<F> {
+    #[inline]
+    pub fn new<C: DynClause<F> + 'static>(clause: C) -> Self {
+        BoxedClause(Box::new(clause))
+    }
+}
+
+impl<F: Source> Clause<F> for BoxedClause<F> {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        self.0.push_clause(buf, idx)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    type Set = BoxedSetter;
+    fn into_types(self) -> Self::Set {
+        self.0.into_setter()
+    }
+}
+
+fn push_joined<F: Source>(clauses: &[BoxedClause<F>], sep: &str,
+                          buf: &mut String, mut idx: usize) -> usize {
+    let mut first = true;
+    for clause in clauses.iter().filter(|c| !Clause::is_empty(*c)) {
+        if !first { buf.push_str(sep); }
+        buf.push_str("(");
+        idx = Clause::push_clause(clause, buf, idx);
+        buf.push_str(")");
+        first = false;
+    }
+    idx
+}
+
+pub struct All<F>(pub Vec<BoxedClause<F>>);
+pub struct Any<F>(pub Vec<BoxedClause<F>>);
+
+impl<F: Source> Clause<F> for All<F> {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        push_joined(&self.0, " AND ", buf, idx)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|c| Clause::is_empty(c))
+    }
+
+    type Set = Vec<BoxedSetter>;
+    fn into_types(self) -> Self::Set {
+        self.0.into_iter().map(Clause::into_types).collect()
+    }
+}
+
+impl<F: Source> Clause<F> for Any<F> {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        push_joined(&self.0, " OR ", buf, idx)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|c| Clause::is_empty(c))
+    }
+
+    type Set = Vec<BoxedSetter>;
+    fn into_types(self) -> Self::Set {
+        self.0.into_iter().map(Clause::into_types).collect()
+    }
+}
+
+pub struct BoxedSelection<F: Source>(Box<dyn Selection<F>>);
+
+impl<F: Source> BoxedSelection<F> {
+    #[inline]
+    pub fn new<S: Selection<F> + 'static>(selection: S) -> Self {
+        BoxedSelection(Box::new(selection))
+    }
+}
+
+impl<F: Source> Selection<F> for BoxedSelection<F> {
+    #[inline]
+    fn push_selection(&self, src: &F, buf: &mut String) -> bool {
+        self.0.push_selection(src, buf)
+    }
+}
+
+impl<F: Source, S: Selection<F>> Selection<F> for Vec<S> {
+    #[inline]
+    fn push_selection(&self, src: &F, buf: &mut String) -> bool {
+        let mut did = false;
+        for selection in self {
+            let len = buf.len();
+            if did { buf.push_str(", "); }
+            if selection.push_selection(src, buf) {
+                did = true;
+            } else {
+                buf.truncate(len);
+            }
+        }
+        did
+    }
+}
+
+pub struct BoxedOrder<F: Source>(Box<dyn OrderSeq<F>>);
+
+impl<F: Source> BoxedOrder<F> {
+    #[inline]
+    pub fn new<O: OrderSeq<F> + 'static>(order: O) -> Self {
+        BoxedOrder(Box::new(order))
+    }
+}
+
+impl<F: Source> OrderSeq<F> for BoxedOrder<F> {
+    #[inline]
+    fn push_seq(&self, buf: &mut String) {
+        self.0.push_seq(buf);
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<F: Source, O: OrderSeq<F>> OrderSeq<F> for Vec<O> {
+    #[inline]
+    fn push_seq(&self, buf: &mut String) {
+        let mut first = true;
+        for order in self.iter().filter(|o| !o.is_empty()) {
+            if !first { buf.push_str(", "); }
+            order.push_seq(buf);
+            first = false;
+        }
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.iter().all(|o| o.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+            (Name, NAME, "name", String),
+        });
+    }
+    use self::users::{Users, ID, NAME};
+
+    // The sql, and the values the query takes.
+    fn render<C: Clause<Users, Set = Vec<BoxedSetter>>>(clause: C) -> (String, usize) {
+        let query = Users.select().selecting(ID).filter(clause);
+        let mut sql = String::new();
+        let idx = query.push_sql(&mut sql, 1);
+        let (_, set) = query.into_types();
+        let mut values: Vec<&ToSql> = vec![];
+        set.push_values(Unit, &mut values);
+        assert_eq!(values.len(), idx - 1, "placeholders of `{}`", sql);
+        (sql, values.len())
+    }
+
+    #[test]
+    fn numbers_collected_clauses() {
+        let clauses = All(vec![
+            BoxedClause::new(ID.equality::<Users>().if_some(None::<i32>)),
+            BoxedClause::new(NAME.equality::<Users>().taking("name".to_string())),
+            BoxedClause::new(Any(vec![
+                BoxedClause::new(ID.equality::<Users>().taking(1)),
+                BoxedClause::new(NAME.equality::<Users>().if_some(None::<String>)),
+                BoxedClause::new(ID.equality::<Users>().taking(2)),
+            ])),
+        ]);
+        assert_eq!(render(clauses), (
+            "SELECT users.id FROM users WHERE (name = $1) AND ((id = $2) OR (id = $3))".to_string(),
+            3,
+        ));
+    }
+
+    #[test]
+    fn drops_empty_collections() {
+        let none: Vec<BoxedClause<Users>> = vec![];
+        assert_eq!(render(All(none)), ("SELECT users.id FROM users".to_string(), 0));
+
+        let skipped = Any(vec![
+            BoxedClause::new(ID.equality::<Users>().if_some(None::<i32>)),
+        ]);
+        assert_eq!(render(skipped), ("SELECT users.id FROM users".to_string(), 0));
+    }
+}
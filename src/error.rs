@@ -0,0 +1,89 @@
+use std::{error, fmt, io};
+use crate::query::TransactionError;
+
+// Errors from preparing or running a query: either the driver's, or a
+// query that can't be rendered (or run) as built.
+#[derive(Debug)]
+pub enum Error {
+    Postgres(postgres::Error),
+    Async(tokio_postgres::Error),
+    // a builder setting (or copying) no columns
+    EmptySelection,
+    // an insert repeated zero times, or a `DEFAULT VALUES` one repeated
+    InvalidRepetitions(usize),
+    // values given for a statement, against its placeholders
+    ParameterCount { expected: usize, found: usize },
+    // the `{}` in a fragment of sql, against the values it takes
+    PlaceholderCount { sql: &'static str, expected: usize, found: usize },
+    // `COPY` data that doesn't decode
+    Copy(io::Error),
+    // rows run against an insert prepared for another `Shape`
+    ShapeMismatch,
+    // a column read from a row that's missing or doesn't decode
+    Decode { column: usize, error: Box<dyn error::Error + Sync + Send> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Postgres(ref e) => write!(f, "{}", e),
+            Error::Async(ref e) => write!(f, "{}", e),
+            Error::EmptySelection => write!(f, "no columns selected"),
+            Error::InvalidRepetitions(reps) =>
+                write!(f, "can not insert {} repetitions of the values", reps),
+            Error::ParameterCount { expected, found } =>
+                write!(f, "expected {} parameters, found {}", expected, found),
+            Error::PlaceholderCount { sql, expected, found } =>
+                write!(f, "expected {} placeholders in `{}`, found {}", expected, sql, found),
+            Error::Copy(ref e) => write!(f, "malformed COPY data: {}", e),
+            Error::ShapeMismatch =>
+                write!(f, "rows don't match the shape the insert was prepared for"),
+            Error::Decode { column, ref error } =>
+                write!(f, "error decoding column {}: {}", column, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Postgres(ref e) => Some(e),
+            Error::Async(ref e) => Some(e),
+            Error::Copy(ref e) => Some(e),
+            Error::Decode { ref error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+impl From<postgres::Error> for Error {
+    fn from(e: postgres::Error) -> Self {
+        Error::Postgres(e)
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(e: tokio_postgres::Error) -> Self {
+        Error::Async(e)
+    }
+}
+
+impl TransactionError for Error {
+    fn sql_state(&self) -> Option<&str> {
+        match *self {
+            Error::Postgres(ref e) => e.sql_state(),
+            Error::Async(ref e) => e.sql_state(),
+            _ => None,
+        }
+    }
+}
+
+// The values pushed for a statement must fill its placeholders.
+#[inline]
+pub(crate) fn check_count(expected: usize, found: usize) -> Result<(), Error> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(Error::ParameterCount { expected, found })
+    }
+}
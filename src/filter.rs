@@ -1,4 +1,4 @@
-use crate::{*, utils::*};
+use crate::{*, utils::*, setters::OptValue, source::Nullable};
 use postgres::types::ToSql;
 
 pub trait Clause<F: Source>: Sized  {
@@ -6,6 +6,13 @@ pub trait Clause<F: Source>: Sized  {
     fn push_clause(&self, buf: &mut String, idx: usize) -> usize;
     fn into_types(self) -> Self::Set;
 
+    // An empty clause renders nothing, and is dropped by `And`, `Or`,
+    // `Not` and the `WHERE` clause.
+    #[inline]
+    fn is_empty(&self) -> bool {
+        false
+    }
+
     #[inline]
     fn and<C: Clause<F>>(self, other: C) -> And<Self, C> {
         And(self, other)
@@ -26,6 +33,12 @@ pub trait Clause<F: Source>: Sized  {
     where Self::Set: Takes<'a, &'a A> {
         WithValue(self, assignment)
     }
+
+    #[inline]
+    fn if_some<'a, A: 'a>(self, assignment: Option<A>) -> OptValue<Self, Option<A>>
+    where Self::Set: Takes<'a, &'a A> {
+        OptValue(self, assignment)
+    }
 }
 
 impl<
@@ -37,18 +50,51 @@ impl<
         self.0.push_clause(buf, idx)
     }
 
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     type Set = WithValue<S::Set, A>;
     fn into_types(self) -> Self::Set {
         WithValue(self.0.into_types(), self.1)
     }
 }
 
+impl<
+    F: Source, A,
+    S: Clause<F>,
+> Clause<F> for OptValue<S, Option<A>> {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        match self.1 {
+            Some(_) => self.0.push_clause(buf, idx),
+            None => idx,
+        }
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.1.is_none() || self.0.is_empty()
+    }
+
+    type Set = OptValue<S::Set, Option<A>>;
+    fn into_types(self) -> Self::Set {
+        OptValue(self.0.into_types(), self.1)
+    }
+}
+
 
 pub struct And<L, R>(L, R);
 
 impl<F: Source, L: Clause<F>, R: Clause<F>> Clause<F> for And<L, R> {
     #[inline]
     fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        if self.0.is_empty() {
+            return self.1.push_clause(buf, idx);
+        } else if self.1.is_empty() {
+            return self.0.push_clause(buf, idx);
+        }
         buf.push_str("(");
         let idx = self.0.push_clause(buf, idx);
         buf.push_str(") AND (");
@@ -56,6 +102,12 @@ impl<F: Source, L: Clause<F>, R: Clause<F>> Clause<F> for And<L, R> {
         buf.push_str(")");
         idx
     }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty() && self.1.is_empty()
+    }
+
     type Set = Seq![L::Set, R::Set];
     fn into_types(self) -> Self::Set {
         seq![self.0.into_types(), self.1.into_types()]
@@ -68,6 +120,11 @@ pub struct Or<L, R>(L, R);
 impl<F: Source, L: Clause<F>, R: Clause<F>> Clause<F> for Or<L, R> {
     #[inline]
     fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        if self.0.is_empty() {
+            return self.1.push_clause(buf, idx);
+        } else if self.1.is_empty() {
+            return self.0.push_clause(buf, idx);
+        }
         buf.push_str("( ");
         let idx = self.0.push_clause(buf, idx);
         buf.push_str(" ) OR ( ");
@@ -75,6 +132,12 @@ impl<F: Source, L: Clause<F>, R: Clause<F>> Clause<F> for Or<L, R> {
         buf.push_str(" )");
         idx
     }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty() && self.1.is_empty()
+    }
+
     type Set = Seq![L::Set, R::Set];
     fn into_types(self) -> Self::Set {
         seq![self.0.into_types(), self.1.into_types()]
@@ -86,11 +149,19 @@ pub struct Not<C>(C);
 impl<F: Source, C: Clause<F>> Clause<F> for Not<C> {
     #[inline]
     fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        if self.0.is_empty() {
+            return idx;
+        }
         buf.push_str("NOT ( ");
         let idx = self.0.push_clause(buf, idx);
         buf.push_str(" )");
         idx
     }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
     type Set = C::Set;
     fn into_types(self) -> Self::Set {
         self.0.into_types()
@@ -107,6 +178,9 @@ pub trait WhereClause<F: Source>  {
 impl<F: Source, C: Clause<F>> WhereClause<F> for Wrap<C> {
     #[inline]
     fn push_where_clause(&self, buf: &mut String, idx: usize) -> usize {
+        if self.0.is_empty() {
+            return idx;
+        }
         buf.push_str(" WHERE ");
         self.0.push_clause(buf, idx)
     }
@@ -133,15 +207,25 @@ impl<C> ColWrap<C> {
         Equality(self)
     }
 
+    pub fn equals<F: Source>(self, value: C::Type) -> WithValue<Equality<Self>, C::Type>
+    where C: Column<F> + Typed {
+        WithValue(Equality(self), value)
+    }
+
     pub fn in_list(self, size: usize) -> InList<Self> {
         InList(self, size)
     }
 
     pub fn is_null<F: Source>(self) -> IsNull<Self>
-    where C: Column<F> {
+    where C: Column<F> + Nullable {
         IsNull(self)
     }
 
+    pub fn equals_column<T: Source, D: Column<T>>(self, other: Qualified<T, D>)
+    -> EqColumn<Self, Qualified<T, D>> {
+        EqColumn(self, other)
+    }
+
     pub fn in_query<'a, Q: IntoSql<Get=Wrap<Self>>>(
             self, query: Q) -> InSubQuery<Self, Q> {
 
@@ -164,6 +248,25 @@ impl<F: Source, C: Column<F>> Clause<F> for Equality<ColWrap<C>> {
     }
 }
 
+pub struct EqColumn<C, D>(C, D);
+
+impl<F: Source, C: Column<F>, T: Source, D: Column<T>>
+Clause<F> for EqColumn<ColWrap<C>, Qualified<T, D>> {
+    #[inline]
+    fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
+        (self.0).0.push_name(buf);
+        buf.push_str(" = ");
+        (self.1).0.push_source(buf);
+        buf.push_str(".");
+        (self.1).1.push_name(buf);
+        idx
+    }
+    type Set = Unit;
+    fn into_types(self) -> Self::Set {
+        Unit
+    }
+}
+
 impl<F: Source, C: Column<F>> Clause<F> for InList<ColWrap<C>> {
     #[inline]
     fn push_clause(&self, buf: &mut String, mut idx: usize) -> usize {
@@ -188,19 +291,15 @@ impl<   'a, S, T: Takes<'a, S>,
 Takes<'a, I> for InList<T> {
 
     fn push_values<'b:'a>(&'b self, values: I, buf: &mut Vec<&'a ToSql>) {
-        let mut cnt = 0;
+        // a count other than the list's is caught by `execute_with`
         for val in values {
             self.0.push_values(val, buf);
-            cnt += 1;
-            if cnt > self.1 {
-                panic!("Arguments count mismatch");
-            }
         }
     }
 
 }
 
-impl<F: Source, C: Column<F>> Clause<F> for IsNull<ColWrap<C>> {
+impl<F: Source, C: Column<F> + Nullable> Clause<F> for IsNull<ColWrap<C>> {
     #[inline]
     fn push_clause(&self, buf: &mut String, idx: usize) -> usize {
         (self.0).0.push_name(buf);
@@ -232,3 +331,68 @@ impl<F: Source, C: Column<F>,
         self.1.into_types().1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod users {
+        table!(Users, "users" => ALL {
+            (Id, ID, "id", i32),
+            (Name, NAME, "name", String),
+        });
+    }
+    use self::users::{Users, ID, NAME};
+
+    // The sql, and the index of the next placeholder.
+    fn render<C: Clause<Users>>(clause: C) -> (String, usize) {
+        let mut sql = String::new();
+        let idx = Users.select().selecting(ID).filter(clause).push_sql(&mut sql, 1);
+        (sql, idx)
+    }
+
+    #[test]
+    fn drops_where_of_skipped_clauses() {
+        assert_eq!(render(ID.equality::<Users>().if_some(None::<i32>)),
+                   ("SELECT users.id FROM users".to_string(), 1));
+        let clause = ID.equality::<Users>().if_some(None::<i32>)
+            .and(NAME.equality::<Users>().if_some(None::<String>))
+            .not();
+        assert_eq!(render(clause), ("SELECT users.id FROM users".to_string(), 1));
+    }
+
+    #[test]
+    fn collapses_skipped_sides() {
+        let clause = ID.equality::<Users>().if_some(None::<i32>)
+            .and(NAME.equality::<Users>());
+        assert_eq!(render(clause), ("SELECT users.id FROM users WHERE name = $1".to_string(), 2));
+
+        let clause = ID.equality::<Users>().if_some(Some(1))
+            .or(NAME.equality::<Users>().if_some(None::<String>));
+        assert_eq!(render(clause), ("SELECT users.id FROM users WHERE id = $1".to_string(), 2));
+
+        let clause = ID.equality::<Users>()
+            .or(NAME.equality::<Users>());
+        let sql = "SELECT users.id FROM users WHERE ( id = $1 ) OR ( name = $2 )";
+        assert_eq!(render(clause), (sql.to_string(), 3));
+    }
+
+    #[test]
+    fn numbers_after_skipped_clauses() {
+        let clause = ID.equality::<Users>().if_some(None::<i32>)
+            .and(NAME.equality::<Users>())
+            .and(ID.equality::<Users>().if_some(Some(2)));
+        assert_eq!(render(clause),
+                   ("SELECT users.id FROM users WHERE (name = $1) AND (id = $2)".to_string(), 3));
+    }
+
+    #[test]
+    fn takes_only_present_values() {
+        let clause = ID.equality::<Users>().if_some(None::<i32>)
+            .and(NAME.equality::<Users>().if_some(Some("name".to_string())));
+        let set = Clause::<Users>::into_types(clause);
+        let mut values: Vec<&ToSql> = vec![];
+        set.push_values(Unit, &mut values);
+        assert_eq!(values.len(), 1);
+    }
+}
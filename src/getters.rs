@@ -1,5 +1,6 @@
 use crate::{*, utils::*};
 use postgres::types::{FromSql};
+use std::marker::PhantomData;
 
 pub trait Selection<F: Source> {
     #[inline]
@@ -25,12 +26,21 @@ impl<F: Source, A: Selection<F>, B: Selection<F>> Selection<F> for Seq<A, B> {
 
 pub trait Makes<'a, S>: Sized {
     fn get<R: Row>(s: &'a S, row: &'a R, idx: usize) -> (Self, usize);
+
+    // Like `get`, reading the columns with `Row::try_get`.
+    fn try_get<R: Row>(s: &'a S, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        Ok(Self::get(s, row, idx))
+    }
 }
 
 impl<'a, S, T: Makes<'a, S>> Makes<'a, &'a S> for T {
     fn get<R: Row>(s: &'a &'a S, row: &'a R, idx: usize) -> (Self, usize) {
         <T as Makes<'a, S>>::get(*s, row, idx)
     }
+
+    fn try_get<R: Row>(s: &'a &'a S, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        <T as Makes<'a, S>>::try_get(*s, row, idx)
+    }
 }
 
 impl<
@@ -43,12 +53,22 @@ impl<
         let (b, idx) = Makes::get(&s.1, row, idx);
         (Seq(a, b), idx)
     }
+
+    fn try_get<R: Row>(s: &'a Seq<S, T>, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        let (a, idx) = Makes::try_get(&s.0, row, idx)?;
+        let (b, idx) = Makes::try_get(&s.1, row, idx)?;
+        Ok((Seq(a, b), idx))
+    }
 }
 
 impl<'a, S, A: Makes<'a, S>> Makes<'a, ColWrap<S>> for A {
     fn get<R: Row>(s: &'a ColWrap<S>, row: &'a R, idx: usize) -> (Self, usize) {
         Makes::get(&s.0, row, idx)
     }
+
+    fn try_get<R: Row>(s: &'a ColWrap<S>, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        Makes::try_get(&s.0, row, idx)
+    }
 }
 
 impl<'a, S, A: Makes<'a, S>> Makes<'a, Wrap<S>> for Wrap<A> {
@@ -56,6 +76,11 @@ impl<'a, S, A: Makes<'a, S>> Makes<'a, Wrap<S>> for Wrap<A> {
         let (el, idx) = Makes::get(&s.0, row, idx);
         (Wrap(el), idx)
     }
+
+    fn try_get<R: Row>(s: &'a Wrap<S>, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        let (el, idx) = Makes::try_get(&s.0, row, idx)?;
+        Ok((Wrap(el), idx))
+    }
 }
 
 pub trait Value: Sized {
@@ -108,6 +133,98 @@ impl<'a, S, A: Makes<'a, S>> Makes<'a, OptionalSelection<S>> for Option<A> {
             (None, idx)
         }
     }
+
+    fn try_get<R: Row>(s: &'a OptionalSelection<S>, row: &'a R, idx: usize)
+    -> Result<(Self, usize), Error> {
+        if s.1 {
+            let (obj, idx) = Makes::try_get(&s.0, row, idx)?;
+            Ok((Some(obj), idx))
+        } else {
+            Ok((None, idx))
+        }
+    }
+}
+
+// A sql expression selected as a single value of type `T`;
+// eg. `expression::<bool>("xmax = 0 AS inserted")`.
+pub struct Expression<T>(&'static str, PhantomData<T>);
+
+#[inline]
+pub fn expression<T>(sql: &'static str) -> Expression<T> {
+    Expression(sql, PhantomData)
+}
+
+impl<F: Source, T> Selection<F> for Expression<T> {
+    #[inline]
+    fn push_selection(&self, _src: &F, buf: &mut String) -> bool {
+        buf.push_str(self.0);
+        true
+    }
+}
+
+impl<'a, T: FromSql<'a>> Makes<'a, Expression<T>> for T {
+    fn get<R: Row>(_s: &'a Expression<T>, row: &'a R, idx: usize) -> (Self, usize) {
+        (row.get(idx), idx + 1)
+    }
+
+    fn try_get<R: Row>(_s: &'a Expression<T>, row: &'a R, idx: usize)
+    -> Result<(Self, usize), Error> {
+        Ok((row.try_get(idx)?, idx + 1))
+    }
+}
+
+// `old.col` and `new.col` in a `RETURNING` clause (Postgres 18+).
+pub struct Old<C>(pub C);
+pub struct New<C>(pub C);
+
+impl<C> ColWrap<C> {
+    #[inline]
+    pub fn old_value(self) -> Old<C> {
+        Old(self.0)
+    }
+
+    #[inline]
+    pub fn new_value(self) -> New<C> {
+        New(self.0)
+    }
+}
+
+impl<F: Source, C: Column<F>> Selection<F> for Old<C> {
+    #[inline]
+    fn push_selection(&self, _src: &F, buf: &mut String) -> bool {
+        buf.push_str("old.");
+        self.0.push_name(buf);
+        true
+    }
+}
+
+impl<F: Source, C: Column<F>> Selection<F> for New<C> {
+    #[inline]
+    fn push_selection(&self, _src: &F, buf: &mut String) -> bool {
+        buf.push_str("new.");
+        self.0.push_name(buf);
+        true
+    }
+}
+
+impl<'a, S, A: Makes<'a, S>> Makes<'a, Old<S>> for A {
+    fn get<R: Row>(s: &'a Old<S>, row: &'a R, idx: usize) -> (Self, usize) {
+        Makes::get(&s.0, row, idx)
+    }
+
+    fn try_get<R: Row>(s: &'a Old<S>, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        Makes::try_get(&s.0, row, idx)
+    }
+}
+
+impl<'a, S, A: Makes<'a, S>> Makes<'a, New<S>> for A {
+    fn get<R: Row>(s: &'a New<S>, row: &'a R, idx: usize) -> (Self, usize) {
+        Makes::get(&s.0, row, idx)
+    }
+
+    fn try_get<R: Row>(s: &'a New<S>, row: &'a R, idx: usize) -> Result<(Self, usize), Error> {
+        Makes::try_get(&s.0, row, idx)
+    }
 }
 
 pub trait Getter {
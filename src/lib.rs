@@ -1,9 +1,9 @@
-#![feature(associated_type_defaults)]
-
 #[macro_use]
 pub mod macros;
 
 pub mod utils;
+pub mod types;
+pub mod error;
 
 pub mod query;
 pub mod source;
@@ -12,15 +12,26 @@ pub mod order_by;
 pub mod filter;
 pub mod setters;
 pub mod builders;
+pub mod dynamic;
+pub mod migrate;
+pub mod schema;
+pub mod cache;
+pub mod copy;
+pub mod cursor;
+#[cfg(any(feature = "r2d2", feature = "bb8"))]
+pub mod pool;
 
 pub use self::{
     utils::{Seq},
-    query::{Row, TypedRow, IntoSql, SqlInput},
-    query::synchronous, // query::asynchronous,
-    source::{Source, Column},
-    getters::{Selection, ReturningClause, Makes, OptionalSelection, Getter},
+    error::Error,
+    query::{Row, TypedRow, RawRow, IntoSql, SqlInput},
+    query::{synchronous, asynchronous},
+    source::{Source, Column, Named, Typed, PrimaryKey, Qualified, Function, Lateral},
+    getters::{Selection, ReturningClause, Makes, OptionalSelection, Getter, expression},
     filter::{Clause, WhereClause, Equality},
-    setters::{ColumnsSetter, Takes, HasSetter, HasOwnedSetter, RefSetter, ValSetter},
+    setters::{ColumnsSetter, Takes, HasSetter, HasOwnedSetter, RefSetter, ValSetter, Params, param},
     order_by::{OrderByClause},
     builders::*,
 };
+
+pub use tygres_derive::{Getter, HasSetter, HasOwnedSetter, Table};
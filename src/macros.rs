@@ -29,18 +29,44 @@ macro_rules! Seq {
     };
 }
 
+// `table!(Name, "name")` declares the table alone; its columns follow
+// with `column!`.  Declared along with its columns, as in
+//
+//     table!(Users, "users" => ALL {
+//         (Id, ID, "id", i32),
+//         (Email, EMAIL, "email", nullable String),
+//     });
+//
+// the table also gets `ALL`, selecting every column in order.
 #[macro_export]
 macro_rules! table {
+    ($name:ident, $table_name: expr => $all:ident {
+        $(($col:ident, $caps:ident, $($column:tt)*)),+ $(,)*
+    }) => {
+        table!($name, $table_name);
+        $(column!($name, $col, $caps, $($column)*);)+
+        all_columns!($all, $($col),+);
+    };
     (*$name:ident, $table_name: expr) => {
         impl $crate::Source for $name {
+            type Set = $crate::utils::Unit;
+
             fn push_source(&self, buf: &mut String) {
                 buf.push_str($table_name);
             }
+
+            fn into_set(self) -> Self::Set {
+                $crate::utils::Unit
+            }
+        }
+
+        impl $crate::source::Named for $name {
+            const NAME: &'static str = $table_name;
         }
     };
     ($name:ident, $table_name: expr) => {
         pub struct $name;
-        table!($name, $table_name);
+        table!(*$name, $table_name);
     };
 }
 
@@ -53,11 +79,53 @@ macro_rules! column {
             }
         }
     };
-    ($table:ident, $name:ident, $caps:ident, $column_name: expr) => {
+    (@decl $table:ident, $name:ident, $caps:ident, $column_name: expr) => {
         pub struct $name;
         pub const $caps: $crate::utils::ColWrap<$name> = $crate::utils::ColWrap($name);
         column!($table, *$name, $column_name);
     };
+    (@typed $name:ident, $ty:ty, $sql_type: expr, $nullable: expr) => {
+        impl $crate::source::Typed for $name {
+            type Type = $ty;
+            const SQL_TYPE: &'static str = $sql_type;
+            const NULLABLE: bool = $nullable;
+        }
+        takes!($name, $ty);
+        makes!($name, $ty);
+    };
+    ($table:ident, $name:ident, $caps:ident, $column_name: expr) => {
+        column!(@decl $table, $name, $caps, $column_name);
+        // untyped columns may or may not hold nulls
+        impl $crate::source::Nullable for $name {}
+    };
+    ($table:ident, $name:ident, $caps:ident, $column_name: expr, nullable $ty:ty) => {
+        column!($table, $name, $caps, $column_name, nullable $ty,
+                <$ty as $crate::types::SqlType>::NAME);
+    };
+    ($table:ident, $name:ident, $caps:ident, $column_name: expr, nullable $ty:ty, $sql_type: expr) => {
+        column!(@decl $table, $name, $caps, $column_name);
+        column!(@typed $name, Option<$ty>, $sql_type, true);
+        // set from present values too, as by `OptValue`
+        takes!($name, $ty);
+        impl $crate::source::Nullable for $name {}
+    };
+    ($table:ident, $name:ident, $caps:ident, $column_name: expr, $ty:ty) => {
+        column!($table, $name, $caps, $column_name, $ty,
+                <$ty as $crate::types::SqlType>::NAME);
+    };
+    ($table:ident, $name:ident, $caps:ident, $column_name: expr, $ty:ty, $sql_type: expr) => {
+        column!(@decl $table, $name, $caps, $column_name);
+        column!(@typed $name, $ty, $sql_type, false);
+    };
+}
+
+// Used by `table!`, which keeps it in step with the columns.
+#[macro_export]
+macro_rules! all_columns {
+    ($caps:ident, $($col:ident),+ $(,)*) => {
+        pub const $caps: Seq![$($crate::utils::ColWrap<$col>),+] =
+            seq![$($crate::utils::ColWrap($col)),+];
+    };
 }
 
 #[macro_export]
@@ -79,6 +147,11 @@ macro_rules! makes {
             fn get<R: $crate::Row>(s: &'a $name, row: &'a R, idx: usize) -> ($ty, usize) {
                 (row.get(idx), idx + 1)
             }
+
+            fn try_get<R: $crate::Row>(_s: &'a $name, row: &'a R, idx: usize)
+            -> Result<($ty, usize), $crate::Error> {
+                Ok((row.try_get(idx)?, idx + 1))
+            }
         }
     }
 }
@@ -0,0 +1,344 @@
+use std::{error, fmt};
+use crate::query::synchronous::Connection;
+
+// Ordered schema migrations, tracked in a table (`tygres_migrations` by
+// default) holding the version, name and a checksum of the sql of every
+// applied migration.  Each migration runs in its own transaction, and
+// runners hold an advisory lock so concurrent ones queue up.
+//
+// Rust migrations have no checksum (it's stored as null), so changes to
+// an applied one go unnoticed; add a migration rather than edit one.
+
+pub type RustAction = fn(&dyn Connection) -> Result<(), Box<dyn error::Error + Sync + Send>>;
+
+pub enum Action {
+    Sql(String),
+    Rust(RustAction),
+}
+
+impl Action {
+    fn run(&self, conn: &dyn Connection) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+        match *self {
+            Action::Sql(ref sql) => Ok(conn.batch_execute(sql)?),
+            Action::Rust(f) => f(conn),
+        }
+    }
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub up: Action,
+    pub down: Option<Action>,
+}
+
+impl Migration {
+    pub fn sql<N: Into<String>, S: Into<String>>(version: i64, name: N, up: S) -> Self {
+        Migration { version, name: name.into(), up: Action::Sql(up.into()), down: None }
+    }
+
+    pub fn rust<N: Into<String>>(version: i64, name: N, up: RustAction) -> Self {
+        Migration { version, name: name.into(), up: Action::Rust(up), down: None }
+    }
+
+    pub fn down_sql<S: Into<String>>(mut self, down: S) -> Self {
+        self.down = Some(Action::Sql(down.into()));
+        self
+    }
+
+    pub fn down_rust(mut self, down: RustAction) -> Self {
+        self.down = Some(Action::Rust(down));
+        self
+    }
+
+    // Only sql migrations are checksummed; rust ones are `None`.
+    pub fn checksum(&self) -> Option<i64> {
+        match self.up {
+            Action::Sql(ref sql) => Some(fnv(sql)),
+            Action::Rust(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Pending,
+    Applied,
+    // applied, but its sql has since changed
+    Modified,
+    // applied, but no longer among the migrations
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub version: i64,
+    pub name: String,
+    pub state: State,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Query(crate::Error),
+    Failed { version: i64, error: Box<dyn error::Error + Sync + Send> },
+    Duplicate(i64),
+    Modified(i64),
+    Missing(i64),
+    Irreversible(i64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Query(ref e) => write!(f, "{}", e),
+            Error::Failed { version, ref error } =>
+                write!(f, "migration {} failed: {}", version, error),
+            Error::Duplicate(version) =>
+                write!(f, "migration {} is declared twice", version),
+            Error::Modified(version) =>
+                write!(f, "migration {} was modified after being applied", version),
+            Error::Missing(version) =>
+                write!(f, "migration {} is applied but not declared", version),
+            Error::Irreversible(version) =>
+                write!(f, "migration {} has no down action", version),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Query(ref e) => Some(e),
+            Error::Failed { ref error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(e: crate::Error) -> Self {
+        Error::Query(e)
+    }
+}
+
+impl From<postgres::Error> for Error {
+    fn from(e: postgres::Error) -> Self {
+        Error::Query(e.into())
+    }
+}
+
+pub struct Migrator {
+    migrations: Vec<Migration>,
+    table: String,
+}
+
+struct Applied {
+    version: i64,
+    name: String,
+    checksum: Option<i64>,
+}
+
+impl Migrator {
+    pub fn new(mut migrations: Vec<Migration>) -> Self {
+        migrations.sort_by_key(|m| m.version);
+        Migrator { migrations, table: "tygres_migrations".into() }
+    }
+
+    // The name of the table, quoted as one identifier.
+    pub fn table<S: Into<String>>(mut self, table: S) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    pub fn status(&self, conn: &dyn Connection) -> Result<Vec<Status>, Error> {
+        self.check_duplicates()?;
+        self.locked(conn, |applied| {
+            let mut out: Vec<_> = self.migrations.iter().map(|m| {
+                let state = match applied.iter().find(|a| a.version == m.version) {
+                    None => State::Pending,
+                    Some(a) if a.checksum != m.checksum() => State::Modified,
+                    Some(_) => State::Applied,
+                };
+                Status { version: m.version, name: m.name.clone(), state }
+            }).collect();
+            out.extend(applied.iter()
+                .filter(|a| self.find(a.version).is_none())
+                .map(|a| Status {
+                    version: a.version, name: a.name.clone(), state: State::Missing,
+                }));
+            out.sort_by_key(|s| s.version);
+            Ok(out)
+        })
+    }
+
+    // Applies every pending migration; returns the versions applied.
+    pub fn up(&self, conn: &dyn Connection) -> Result<Vec<i64>, Error> {
+        self.up_to(conn, i64::max_value())
+    }
+
+    pub fn up_to(&self, conn: &dyn Connection, version: i64) -> Result<Vec<i64>, Error> {
+        self.check_duplicates()?;
+        self.locked(conn, |applied| {
+            for a in applied {
+                match self.find(a.version) {
+                    Some(m) if m.checksum() != a.checksum => return Err(Error::Modified(a.version)),
+                    _ => {},
+                }
+            }
+
+            let mut done = vec![];
+            let pending = self.migrations.iter()
+                .filter(|m| m.version <= version)
+                .filter(|m| applied.iter().all(|a| a.version != m.version));
+            for m in pending {
+                let tx = conn.transaction()?;
+                m.up.run(&tx).map_err(|error| Error::Failed { version: m.version, error })?;
+                tx.execute(
+                    &format!("INSERT INTO {} (version, name, checksum) VALUES ($1, $2, $3)",
+                             self.quoted_table()),
+                    &[&m.version, &m.name, &m.checksum()])?;
+                tx.commit()?;
+                done.push(m.version);
+            }
+            Ok(done)
+        })
+    }
+
+    // Reverts the latest applied migration, if any.
+    pub fn down(&self, conn: &dyn Connection) -> Result<Option<i64>, Error> {
+        self.check_duplicates()?;
+        self.locked(conn, |applied| match applied.last() {
+            Some(a) => self.revert(conn, a.version).map(Some),
+            None => Ok(None),
+        })
+    }
+
+    // Reverts every applied migration newer than `version`; returns the
+    // versions reverted, latest first.
+    pub fn down_to(&self, conn: &dyn Connection, version: i64) -> Result<Vec<i64>, Error> {
+        self.check_duplicates()?;
+        self.locked(conn, |applied| {
+            applied.iter().rev()
+                .take_while(|a| a.version > version)
+                .map(|a| self.revert(conn, a.version))
+                .collect()
+        })
+    }
+
+    fn revert(&self, conn: &dyn Connection, version: i64) -> Result<i64, Error> {
+        let m = self.find(version).ok_or(Error::Missing(version))?;
+        let down = m.down.as_ref().ok_or(Error::Irreversible(version))?;
+        let tx = conn.transaction()?;
+        down.run(&tx).map_err(|error| Error::Failed { version, error })?;
+        tx.execute(&format!("DELETE FROM {} WHERE version = $1", self.quoted_table()),
+                   &[&version])?;
+        tx.commit()?;
+        Ok(version)
+    }
+
+    fn quoted_table(&self) -> String {
+        format!("\"{}\"", self.table.replace('"', "\"\""))
+    }
+
+    fn find(&self, version: i64) -> Option<&Migration> {
+        self.migrations.iter().find(|m| m.version == version)
+    }
+
+    fn check_duplicates(&self) -> Result<(), Error> {
+        match self.migrations.windows(2).find(|w| w[0].version == w[1].version) {
+            Some(w) => Err(Error::Duplicate(w[0].version)),
+            None => Ok(()),
+        }
+    }
+
+    // Runs `f` on the applied migrations while holding the advisory lock.
+    fn locked<T, F>(&self, conn: &dyn Connection, f: F) -> Result<T, Error>
+    where F: FnOnce(&[Applied]) -> Result<T, Error> {
+        let key = fnv(&self.table);
+        conn.execute("SELECT pg_advisory_lock($1)", &[&key])?;
+        let result = self.applied(conn).and_then(|applied| f(&applied));
+        let unlocked = conn.execute("SELECT pg_advisory_unlock($1)", &[&key]);
+        let out = result?;
+        unlocked?;
+        Ok(out)
+    }
+
+    fn applied(&self, conn: &dyn Connection) -> Result<Vec<Applied>, Error> {
+        conn.batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                version bigint PRIMARY KEY,
+                name text NOT NULL,
+                checksum bigint,
+                applied_at timestamptz NOT NULL DEFAULT now()
+            )", self.quoted_table()))?;
+        let rows = conn.query(
+            &format!("SELECT version, name, checksum FROM {} ORDER BY version",
+                     self.quoted_table()),
+            &[])?;
+        Ok(rows.iter().map(|row| Applied {
+            version: row.get(0),
+            name: row.get(1),
+            checksum: row.get(2),
+        }).collect())
+    }
+}
+
+// 64-bit FNV-1a
+fn fnv(s: &str) -> i64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in s.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_sql_only() {
+        let sql = Migration::sql(1, "users", "CREATE TABLE users ()");
+        assert_eq!(sql.checksum(), Some(fnv("CREATE TABLE users ()")));
+        let changed = Migration::sql(1, "users", "CREATE TABLE users (id int)");
+        assert_ne!(sql.checksum(), changed.checksum());
+
+        fn noop(_: &dyn Connection) -> Result<(), Box<dyn error::Error + Sync + Send>> {
+            Ok(())
+        }
+        assert_eq!(Migration::rust(2, "backfill", noop).checksum(), None);
+    }
+
+    #[test]
+    fn fnv_matches_reference() {
+        assert_eq!(fnv("") as u64, 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv("a") as u64, 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn sorts_and_rejects_duplicates() {
+        let migrator = Migrator::new(vec![
+            Migration::sql(2, "b", ""),
+            Migration::sql(1, "a", ""),
+        ]);
+        assert_eq!(migrator.migrations.iter().map(|m| m.version).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(migrator.check_duplicates().is_ok());
+
+        let migrator = Migrator::new(vec![
+            Migration::sql(1, "a", ""),
+            Migration::sql(1, "b", ""),
+        ]);
+        match migrator.check_duplicates() {
+            Err(Error::Duplicate(1)) => {},
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quotes_table() {
+        assert_eq!(Migrator::new(vec![]).quoted_table(), "\"tygres_migrations\"");
+        let migrator = Migrator::new(vec![]).table("x\"; DROP TABLE users; --");
+        assert_eq!(migrator.quoted_table(), "\"x\"\"; DROP TABLE users; --\"");
+    }
+}
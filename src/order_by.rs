@@ -3,6 +3,11 @@ use crate::{*, utils::*};
 pub trait OrderSeq<F: Source> {
     #[inline]
     fn push_seq(&self, buf: &mut String);
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        false
+    }
 }
 
 impl<F: Source, A: OrderSeq<F>, B: OrderSeq<F>> OrderSeq<F> for Seq<A, B> {
@@ -27,6 +32,9 @@ impl<F: Source> OrderByClause<F> for Unit {
 impl<F: Source, O: OrderSeq<F>> OrderByClause<F> for Wrap<O> {
     #[inline]
     fn push_order_by(&self, buf: &mut String) {
+        if self.0.is_empty() {
+            return;
+        }
         buf.push_str(" ORDER BY ");
         self.0.push_seq(buf);
     }
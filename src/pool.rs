@@ -0,0 +1,108 @@
+// Connection managers for the `r2d2` (synchronous) and `bb8`
+// (asynchronous) pools.  Both validate connections with a health check
+// query before handing them out.  Pooled sync connections are plain
+// `postgres::Connection`s; a bounded cache is a `StatementCache` built
+// over one per checkout (`StatementCache::new(&*conn, size)`), whose
+// statements go with it.  Async ones are `CachedClient`s, keeping their
+// cache across checkouts.  Connections are plain (no tls).
+
+#[cfg(feature = "r2d2")]
+pub use self::sync::ConnectionManager;
+#[cfg(feature = "bb8")]
+pub use self::async_pool::AsyncConnectionManager;
+
+const HEALTH_CHECK: &str = "SELECT 1";
+
+#[cfg(feature = "r2d2")]
+mod sync {
+    use postgres::{Connection, Error, TlsMode};
+
+    pub struct ConnectionManager {
+        params: String,
+        health_check: String,
+    }
+
+    impl ConnectionManager {
+        pub fn new<S: Into<String>>(params: S) -> Self {
+            ConnectionManager {
+                params: params.into(),
+                health_check: super::HEALTH_CHECK.into(),
+            }
+        }
+
+        pub fn health_check<S: Into<String>>(self, sql: S) -> Self {
+            ConnectionManager { health_check: sql.into(), ..self }
+        }
+    }
+
+    impl r2d2::ManageConnection for ConnectionManager {
+        type Connection = Connection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<Connection, Error> {
+            Connection::connect(&self.params[..], TlsMode::None)
+        }
+
+        fn is_valid(&self, conn: &mut Connection) -> Result<(), Error> {
+            conn.batch_execute(&self.health_check)
+        }
+
+        fn has_broken(&self, conn: &mut Connection) -> bool {
+            conn.is_desynchronized()
+        }
+    }
+}
+
+#[cfg(feature = "bb8")]
+mod async_pool {
+    use async_trait::async_trait;
+    use futures03::compat::Future01CompatExt;
+    use tokio_postgres::{Error, NoTls};
+    use crate::{cache::CachedClient, query::asynchronous::drive};
+
+    pub struct AsyncConnectionManager {
+        params: String,
+        health_check: String,
+        cache_size: usize,
+    }
+
+    impl AsyncConnectionManager {
+        pub fn new<S: Into<String>>(params: S) -> Self {
+            AsyncConnectionManager {
+                params: params.into(),
+                health_check: super::HEALTH_CHECK.into(),
+                cache_size: 64,
+            }
+        }
+
+        pub fn health_check<S: Into<String>>(self, sql: S) -> Self {
+            AsyncConnectionManager { health_check: sql.into(), ..self }
+        }
+
+        // The size of each connection's statement cache.
+        pub fn cache_size(self, cache_size: usize) -> Self {
+            AsyncConnectionManager { cache_size, ..self }
+        }
+    }
+
+    #[async_trait]
+    impl bb8::ManageConnection for AsyncConnectionManager {
+        type Connection = CachedClient;
+        type Error = Error;
+
+        async fn connect(&self) -> Result<CachedClient, Error> {
+            let (client, connection) = tokio_postgres::connect(&self.params, NoTls)
+                .compat().await?;
+            tokio::spawn(drive(connection));
+            Ok(CachedClient::new(client, self.cache_size))
+        }
+
+        async fn is_valid(&self, conn: &mut CachedClient) -> Result<(), Error> {
+            conn.client().batch_execute(&self.health_check).compat().await
+        }
+
+        fn has_broken(&self, conn: &mut CachedClient) -> bool {
+            conn.client().is_closed()
+        }
+    }
+}
@@ -1,56 +1,45 @@
-use crate::{*, utils::*};
-use futures::{Future, Poll, Async, Stream};
-use tokio_postgres::{
-    Client, Prepare, Statement, Query, Execute,
-    error::Error,
+use crate::{*, utils::*, error::check_count};
+use super::{synchronous::TransactionOptions, is_retryable};
+pub use super::TransactionError;
+use std::{future::Future, sync::Arc};
+use futures03::{
+    Stream, TryStreamExt, future::{self, Either}, stream,
+    compat::{Future01CompatExt, Stream01CompatExt},
 };
+use tokio_postgres::{Client, Statement, error::Error as PgError};
+
+// tokio-postgres still speaks futures 0.1; everything here is adapted to
+// std futures, so it can be awaited from async code.  The connection
+// half of a client must be driven too; see `drive`.
 
 pub trait Asynchronous: IntoSql + Sized {
 
     fn prepare(self, cl: &mut Client)
-    -> PrepareFuture<Self::Get, Self::Set> {
+    -> impl Future<Output = Result<Prepared<Self::Get, Self::Set>, Error>> {
 
+        let checked = self.check();
         let mut sql: String = String::with_capacity(0x1000);
         let idx = self.push_sql(&mut sql, 1);
-        let (get, set) = self.into_types();
-
-        PrepareFuture {
-            prepare: cl.prepare(&sql),
-            types: Some((get, set)),
-            setter_count: idx - 1,
+        let (getter, setter) = self.into_types();
+        let prepare = checked.map(|()| cl.prepare(&sql).compat());
+
+        async move {
+            Ok(Prepared {
+                statement: prepare?.await?,
+                getter, setter,
+                setter_count: idx - 1,
+            })
         }
     }
 }
 
 impl<T: IntoSql + Sized> Asynchronous for T {}
 
-pub struct PrepareFuture<Get, Set> {
-    pub types: Option<(Get, Set)>,
-    pub prepare: Prepare,
-    pub setter_count: usize,
-}
-
-impl<Get, Set> Future for PrepareFuture<Get, Set> {
-    type Item = Prepared<Get, Set>;
-    type Error = Error;
-
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match self.prepare.poll() {
-            Ok(Async::Ready(statement)) => {
-                let types = std::mem::replace(&mut self.types, None);
-                let (getter, setter) = match types {
-                    Some(t) => t,
-                    _ => panic!("PrepareFuture polled without types."),
-                };
-                Ok(Async::Ready(Prepared {
-                    statement, getter, setter,
-                    setter_count: self.setter_count,
-                }))
-            },
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(e) => Err(e),
-        }
-    }
+// Adapts the (futures 0.1) connection future of a client, to be spawned
+// on a std futures executor such as tokio.
+pub fn drive<C>(connection: C) -> impl Future<Output = Result<(), PgError>>
+where C: futures::Future<Item = (), Error = PgError> {
+    connection.compat()
 }
 
 pub struct Prepared<Get, Set> {
@@ -61,57 +50,183 @@ pub struct Prepared<Get, Set> {
 }
 
 impl<Get, Set> Prepared<Get, Set> {
-    pub fn execute_with<'a, A>(&'a self, cl: &mut Client, assignment: A) -> Execute
+    pub fn execute_with<'a, A>(&'a self, cl: &mut Client, assignment: A)
+    -> impl Future<Output = Result<u64, Error>>
     where Set: Takes<'a, A> {
 
+        let checked = self.setter.check_values(&assignment);
         let mut values = Vec::with_capacity(self.setter_count);
         self.setter.push_values(assignment, &mut values);
-        cl.execute(&self.statement, &values[..])
+        let request = checked
+            .and_then(|()| check_count(self.setter_count, values.len()))
+            .map(|()| cl.execute(&self.statement, &values[..]).compat());
+        async move { Ok(request?.await?) }
     }
 
-    pub fn query_with<'a, A>(&'a self, cl: &mut Client, assignment: A) -> QueryStream<'a, Get>
+    pub fn query_with<'a, A>(&'a self, cl: &mut Client, assignment: A)
+    -> impl Stream<Item = Result<TypedRow<'a, Get, tokio_postgres::Row>, Error>> + 'a
     where Set: Takes<'a, A> {
+        let checked = self.setter.check_values(&assignment);
         let mut values = Vec::with_capacity(self.setter_count);
         self.setter.push_values(assignment, &mut values);
-
-        QueryStream {
-            query: cl.query(&self.statement, &values[..]),
-            getter: &self.getter,
+        if let Err(e) = checked.and_then(|()| check_count(self.setter_count, values.len())) {
+            return Either::Left(stream::once(future::ready(Err(e))));
         }
 
+        let getter = &self.getter;
+        Either::Right(cl.query(&self.statement, &values[..])
+            .compat()
+            .map_err(Error::from)
+            .map_ok(move |row| TypedRow(getter, row)))
+    }
+
+    pub async fn query_all_with<'a, A>(&'a self, cl: &mut Client, assignment: A)
+    -> Result<Vec<TypedRow<'a, Get, tokio_postgres::Row>>, Error>
+    where Set: Takes<'a, A> {
+        self.query_with(cl, assignment).try_collect().await
     }
 }
 
 impl<Get, Set> Prepared<Get, Set>
 where Set: for<'a> Takes<'a, Unit> {
-    pub fn execute(&self, cl: &mut Client) -> Execute {
+    pub fn execute(&self, cl: &mut Client) -> impl Future<Output = Result<u64, Error>> {
         self.execute_with(cl, Unit)
     }
 
-    pub fn query<'a>(&'a self, cl: &mut Client) -> QueryStream<'a, Get> {
+    pub fn query<'a>(&'a self, cl: &mut Client)
+    -> impl Stream<Item = Result<TypedRow<'a, Get, tokio_postgres::Row>, Error>> + 'a {
         self.query_with(cl, Unit)
     }
+
+    pub async fn query_all<'a>(&'a self, cl: &mut Client)
+    -> Result<Vec<TypedRow<'a, Get, tokio_postgres::Row>>, Error> {
+        self.query_with(cl, Unit).try_collect().await
+    }
 }
 
+// Polls a request once, which queues it on the connection without
+// waiting for the response.
+pub(crate) fn send<F: futures::Future>(request: F) {
+    struct Noop;
+    impl futures::executor::Notify for Noop {
+        fn notify(&self, _id: usize) {}
+    }
+    let _ = futures::executor::spawn(request).poll_future_notify(&Arc::new(Noop), 0);
+}
 
-pub struct QueryStream<'a, Get> {
-    getter: &'a Get,
-    query: Query,
+// A transaction (or, when nested with `savepoint`, a savepoint) on a
+// client, to end with `commit` or `rollback`.  Dropping it unfinished
+// (eg. a cancelled future) is a last resort: the rollback is sent
+// without waiting for it, and its error is lost.
+pub struct Transaction<'c> {
+    client: &'c mut Client,
+    depth: usize,
+    done: bool,
 }
 
-impl<'a, Get> Stream for QueryStream<'a, Get> {
-    type Item = TypedRow<'a, Get, tokio_postgres::Row>;
-    type Error = Error;
-
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Error> {
-        match self.query.poll() {
-            Ok(Async::Ready(Some(row)))
-                => Ok(Async::Ready(Some(
-                    TypedRow(&self.getter, row)
-                ))),
-            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(e) => Err(e),
+impl<'c> Transaction<'c> {
+    pub async fn begin(cl: &'c mut Client) -> Result<Transaction<'c>, PgError> {
+        Self::begin_with(cl, &TransactionOptions::default()).await
+    }
+
+    pub async fn begin_with(cl: &'c mut Client, options: &TransactionOptions)
+    -> Result<Transaction<'c>, PgError> {
+        let modes = options.modes();
+        let sql = if modes.is_empty() {
+            "BEGIN".to_string()
+        } else {
+            format!("BEGIN {}", modes.join(", "))
+        };
+        cl.batch_execute(&sql).compat().await?;
+        Ok(Transaction { client: cl, depth: 0, done: false })
+    }
+
+    pub fn client(&mut self) -> &mut Client {
+        self.client
+    }
+
+    pub fn prepare<Q: IntoSql>(&mut self, query: Q)
+    -> impl Future<Output = Result<Prepared<Q::Get, Q::Set>, Error>> {
+        query.prepare(self.client)
+    }
+
+    pub fn execute_with<'a, Get, Set, A>(&mut self, stmt: &'a Prepared<Get, Set>, assignment: A)
+    -> impl Future<Output = Result<u64, Error>>
+    where Set: Takes<'a, A> {
+        stmt.execute_with(self.client, assignment)
+    }
+
+    pub fn query_with<'a, Get, Set, A>(&mut self, stmt: &'a Prepared<Get, Set>, assignment: A)
+    -> impl Stream<Item = Result<TypedRow<'a, Get, tokio_postgres::Row>, Error>> + 'a
+    where Set: Takes<'a, A> {
+        stmt.query_with(self.client, assignment)
+    }
+
+    pub async fn savepoint(&mut self) -> Result<Transaction<'_>, PgError> {
+        let depth = self.depth + 1;
+        self.client.batch_execute(&format!("SAVEPOINT tygres_{}", depth)).compat().await?;
+        Ok(Transaction { client: &mut *self.client, depth, done: false })
+    }
+
+    fn finish_sql(&self, commit: bool) -> String {
+        match (self.depth, commit) {
+            (0, true) => "COMMIT".to_string(),
+            (0, false) => "ROLLBACK".to_string(),
+            (depth, true) => format!("RELEASE SAVEPOINT tygres_{}", depth),
+            (depth, false) => format!(
+                "ROLLBACK TO SAVEPOINT tygres_{0}; RELEASE SAVEPOINT tygres_{0}", depth),
         }
     }
+
+    pub async fn commit(mut self) -> Result<(), PgError> {
+        self.done = true;
+        let sql = self.finish_sql(true);
+        self.client.batch_execute(&sql).compat().await
+    }
+
+    pub async fn rollback(mut self) -> Result<(), PgError> {
+        self.done = true;
+        let sql = self.finish_sql(false);
+        self.client.batch_execute(&sql).compat().await
+    }
+}
+
+impl<'c> Drop for Transaction<'c> {
+    fn drop(&mut self) {
+        if !self.done {
+            let sql = self.finish_sql(false);
+            send(self.client.batch_execute(&sql));
+        }
+    }
+}
+
+// Runs `f` in a transaction, committing if it returns `Ok` and rolling
+// back otherwise; reruns it on serialization failures and deadlocks.  A
+// failed rollback leaves the client in the transaction, so its error is
+// returned in place of `f`'s, without a rerun.
+pub async fn transaction_with<T, E, F>(cl: &mut Client, options: &TransactionOptions, mut f: F)
+-> Result<T, E>
+where E: TransactionError + From<PgError>,
+      F: AsyncFnMut(&mut Transaction<'_>) -> Result<T, E> {
+    let mut attempt = 0;
+    loop {
+        let mut tx = Transaction::begin_with(&mut *cl, options).await?;
+        let result = match f(&mut tx).await {
+            Ok(value) => tx.commit().await.map(|()| value).map_err(E::from),
+            Err(e) => match tx.rollback().await {
+                Ok(()) => Err(e),
+                Err(rollback) => return Err(rollback.into()),
+            },
+        };
+        match result {
+            Err(ref e) if attempt < options.max_retries() && is_retryable(e) => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+pub async fn transaction<T, E, F>(cl: &mut Client, f: F) -> Result<T, E>
+where E: TransactionError + From<PgError>,
+      F: AsyncFnMut(&mut Transaction<'_>) -> Result<T, E> {
+    transaction_with(cl, &TransactionOptions::default(), f).await
 }
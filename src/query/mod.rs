@@ -1,5 +1,6 @@
+use std::sync::Arc;
 use crate::{*, utils::*};
-use postgres::types::{FromSql, ToSql};
+use postgres::types::{FromSql, ToSql, Type};
 
 pub struct SqlInput<V, W, L, O> {
     pub values: V,
@@ -26,6 +27,11 @@ pub trait IntoSql: Sized {
     type Get;
     type Set;
 
+    // Whether the query can be rendered as built; checked when preparing.
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn push_sql(&self, buf: &mut String, idx: usize) -> usize;
     fn into_types(self) -> (Self::Get, Self::Set);
 }
@@ -33,16 +39,81 @@ pub trait IntoSql: Sized {
 pub trait Row {
     fn get<'b, T>(&'b self, idx: usize) -> T
     where T: FromSql<'b>;
+
+    // Like `get`, but a column that's missing or doesn't decode as `T` is
+    // an error instead of a panic.
+    fn try_get<'b, T>(&'b self, idx: usize) -> Result<T, Error>
+    where T: FromSql<'b>;
+}
+
+fn no_column(idx: usize) -> Error {
+    Error::Decode { column: idx, error: "no such column".into() }
 }
 
 impl Row for tokio_postgres::Row {
     fn get<'b, T>(&'b self, idx: usize) -> T
     where T: FromSql<'b> { self.get(idx) }
+
+    fn try_get<'b, T>(&'b self, idx: usize) -> Result<T, Error>
+    where T: FromSql<'b> {
+        self.try_get(idx)?.ok_or_else(|| no_column(idx))
+    }
 }
 
 impl<'a> Row for postgres::rows::Row<'a> {
     fn get<'b, T>(&'b self, idx: usize) -> T
     where T: FromSql<'b> { self.get(idx) }
+
+    fn try_get<'b, T>(&'b self, idx: usize) -> Result<T, Error>
+    where T: FromSql<'b> {
+        Ok(self.get_opt(idx).ok_or_else(|| no_column(idx))??)
+    }
+}
+
+// A row detached from its connection, as decoded from the binary COPY
+// format: the raw value (or null) of each column, along with its type.
+pub struct RawRow {
+    types: Arc<Vec<Type>>,
+    data: Vec<u8>,
+    fields: Vec<Option<(usize, usize)>>,
+}
+
+impl RawRow {
+    pub fn new(types: Arc<Vec<Type>>, data: Vec<u8>, fields: Vec<Option<(usize, usize)>>) -> Self {
+        RawRow { types, data, fields }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl Row for RawRow {
+    fn get<'b, T>(&'b self, idx: usize) -> T
+    where T: FromSql<'b> {
+        self.try_get(idx).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_get<'b, T>(&'b self, idx: usize) -> Result<T, Error>
+    where T: FromSql<'b> {
+        let (ty, field) = match (self.types.get(idx), self.fields.get(idx)) {
+            (Some(ty), Some(field)) => (ty, field),
+            _ => return Err(no_column(idx)),
+        };
+        if !T::accepts(ty) {
+            let error = format!("can not decode a value of type {}", ty).into();
+            return Err(Error::Decode { column: idx, error });
+        }
+        let value = match *field {
+            Some((start, end)) => T::from_sql(ty, &self.data[start..end]),
+            None => T::from_sql_null(ty),
+        };
+        value.map_err(|error| Error::Decode { column: idx, error })
+    }
 }
 
 pub struct TypedRow<'a, Get, R: Row>(pub &'a Get, pub R);
@@ -52,6 +123,36 @@ impl<'a, Get, R: Row> TypedRow<'a, Get, R> {
     where Wrap<B>: Makes<'a, Get> {
         (<Wrap<B> as Makes<'a, Get>>::get(&self.0, &self.1, 0).0).0
     }
+
+    // Like `as_value`, but a column that's missing or doesn't decode is
+    // an error instead of a panic.
+    pub fn try_as_value<B>(&'a self) -> Result<B, Error>
+    where Wrap<B>: Makes<'a, Get> {
+        Ok((<Wrap<B> as Makes<'a, Get>>::try_get(&self.0, &self.1, 0)?.0).0)
+    }
+}
+
+// Errors that may come from the server; a transaction is rerun on those
+// that are serialization failures or deadlocks.
+pub trait TransactionError {
+    fn sql_state(&self) -> Option<&str>;
+}
+
+impl TransactionError for postgres::Error {
+    fn sql_state(&self) -> Option<&str> {
+        self.code().map(|state| state.code())
+    }
+}
+
+impl TransactionError for tokio_postgres::Error {
+    fn sql_state(&self) -> Option<&str> {
+        self.code().map(|state| state.code())
+    }
+}
+
+pub(crate) fn is_retryable<E: TransactionError>(err: &E) -> bool {
+    // serialization_failure, deadlock_detected
+    err.sql_state().is_some_and(|state| state == "40001" || state == "40P01")
 }
 
 pub mod synchronous;
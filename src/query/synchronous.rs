@@ -1,23 +1,50 @@
-use crate::{*, utils::*};
+use std::rc::Rc;
+use crate::{*, utils::*, cache::Cached};
 pub use postgres::GenericConnection as Connection;
+pub use postgres::transaction::Transaction;
 use postgres::{
     stmt::Statement as PgStatement,
-    Error, rows::{Rows, Iter},
+    Error as PgError, rows::{Rows, Iter, LazyRows},
 };
+use crate::error::check_count;
+use super::is_retryable;
+pub use super::TransactionError;
+use fallible_iterator::FallibleIterator;
 
 pub trait Synchronous: IntoSql + Sized {
 
     fn prepare(self, conn: &dyn Connection)
     -> Result<Statement<Self::Get, Self::Set>, Error> {
 
+        self.check()?;
         let mut sql: String = String::with_capacity(0x1000);
         let idx = self.push_sql(&mut sql, 1);
         let (getter, setter) = self.into_types();
 
         Ok(Statement{
-            statement: conn.prepare(&sql)?,
+            statement: Rc::new(conn.prepare(&sql)?),
             getter, setter,
             setter_count: idx - 1,
+            cached: None,
+        })
+
+    }
+
+    // Like `prepare`, through the connection's own cache, which is
+    // unbounded and never drops stale plans; `StatementCache` is bounded.
+    fn prepare_cached(self, conn: &dyn Connection)
+    -> Result<Statement<Self::Get, Self::Set>, Error> {
+
+        self.check()?;
+        let mut sql: String = String::with_capacity(0x1000);
+        let idx = self.push_sql(&mut sql, 1);
+        let (getter, setter) = self.into_types();
+
+        Ok(Statement{
+            statement: Rc::new(conn.prepare_cached(&sql)?),
+            getter, setter,
+            setter_count: idx - 1,
+            cached: None,
         })
 
     }
@@ -27,28 +54,67 @@ pub trait Synchronous: IntoSql + Sized {
 impl<T: IntoSql + Sized> Synchronous for T {}
 
 pub struct Statement<'a, Get, Set> {
-    pub statement: PgStatement<'a>,
+    pub statement: Rc<PgStatement<'a>>,
     pub getter: Get,
     pub setter: Set,
     setter_count: usize,
+    cached: Option<Cached<'a>>,
 }
 
 impl<'a, Get, Set> Statement<'a, Get, Set> {
+    pub(crate) fn from_cache(statement: Rc<PgStatement<'a>>, getter: Get, setter: Set,
+                             setter_count: usize, cached: Cached<'a>) -> Self {
+        Statement { statement, getter, setter, setter_count, cached: Some(cached) }
+    }
+
+    // A cached statement whose plan went stale is dropped from its cache.
+    fn check(&self, err: PgError) -> Error {
+        if let Some(ref cached) = self.cached {
+            cached.check(&err);
+        }
+        err.into()
+    }
+
     pub fn execute_with<'b, A>(&'b self, assignment: A) -> Result<u64, Error>
     where Set: Takes<'b, A> {
+        self.setter.check_values(&assignment)?;
         let mut values = Vec::with_capacity(self.setter_count);
         self.setter.push_values(assignment, &mut values);
-        self.statement.execute(&values[..])
+        check_count(self.setter_count, values.len())?;
+        self.statement.execute(&values[..]).map_err(|e| self.check(e))
     }
 
     pub fn query_with<'b, A>(&'b self, assignment: A)
     -> Result<QueryRows<'b, Get>, Error>
     where Set: Takes<'b, A> {
+        self.setter.check_values(&assignment)?;
         let mut values = Vec::with_capacity(self.setter_count);
         self.setter.push_values(assignment, &mut values);
+        check_count(self.setter_count, values.len())?;
 
         Ok(QueryRows{
-            rows: self.statement.query(&values[..])?,
+            rows: self.statement.query(&values[..]).map_err(|e| self.check(e))?,
+            getter: &self.getter,
+        })
+    }
+}
+
+impl<'a, Get, Set> Statement<'a, Get, Set> {
+    // Like `query_with`, but through a portal, fetching `row_limit` rows at
+    // a time instead of buffering them all; must run in a transaction
+    // (`trans`) of the connection the statement was prepared on.
+    pub fn lazy_query_with<'b, 'trans, A>(&'b self, trans: &'trans Transaction, row_limit: i32,
+                                          assignment: A)
+    -> Result<LazyQueryRows<'trans, 'b, Get>, Error>
+    where Set: Takes<'b, A> {
+        self.setter.check_values(&assignment)?;
+        let mut values = Vec::with_capacity(self.setter_count);
+        self.setter.push_values(assignment, &mut values);
+        check_count(self.setter_count, values.len())?;
+
+        Ok(LazyQueryRows{
+            rows: self.statement.lazy_query(trans, &values[..], row_limit)
+                .map_err(|e| self.check(e))?,
             getter: &self.getter,
         })
     }
@@ -64,6 +130,12 @@ impl<'a, Get, Set> Statement<'a, Get, Set> {
     where Set: Takes<'b, Unit> {
         self.query_with(Unit)
     }
+
+    pub fn lazy_query<'b, 'trans>(&'b self, trans: &'trans Transaction, row_limit: i32)
+    -> Result<LazyQueryRows<'trans, 'b, Get>, Error>
+    where Set: Takes<'b, Unit> {
+        self.lazy_query_with(trans, row_limit, Unit)
+    }
 }
 
 pub struct QueryRows<'a, Get> {
@@ -111,3 +183,198 @@ impl<'a, Get> Iterator for QueryIter<'a, Get> {
     }
 
 }
+
+pub struct LazyQueryRows<'trans, 'stmt, Get> {
+    rows: LazyRows<'trans, 'stmt>,
+    getter: &'stmt Get,
+}
+
+impl<'trans, 'stmt, Get> Iterator for LazyQueryRows<'trans, 'stmt, Get> {
+    type Item = Result<TypedRow<'stmt, Get, postgres::rows::Row<'stmt>>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.next() {
+            Ok(Some(row)) => Some(Ok(TypedRow(self.getter, row))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+// The isolation level, access mode and retries of the top level
+// transaction `run` begins; a `savepoint` takes none of them.
+#[derive(Clone, Debug)]
+pub struct TransactionOptions {
+    isolation: Option<IsolationLevel>,
+    read_only: bool,
+    deferrable: bool,
+    retries: usize,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        TransactionOptions {
+            isolation: None,
+            read_only: false,
+            deferrable: false,
+            retries: 3,
+        }
+    }
+}
+
+impl TransactionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn isolation(self, level: IsolationLevel) -> Self {
+        TransactionOptions { isolation: Some(level), ..self }
+    }
+
+    pub fn read_only(self, read_only: bool) -> Self {
+        TransactionOptions { read_only, ..self }
+    }
+
+    pub fn deferrable(self, deferrable: bool) -> Self {
+        TransactionOptions { deferrable, ..self }
+    }
+
+    // How many times to rerun the closure after a serialization failure
+    // or deadlock.
+    pub fn retries(self, retries: usize) -> Self {
+        TransactionOptions { retries, ..self }
+    }
+
+    pub(crate) fn modes(&self) -> Vec<&'static str> {
+        let mut modes = vec![];
+        match self.isolation {
+            Some(IsolationLevel::ReadCommitted) => modes.push("ISOLATION LEVEL READ COMMITTED"),
+            Some(IsolationLevel::RepeatableRead) => modes.push("ISOLATION LEVEL REPEATABLE READ"),
+            Some(IsolationLevel::Serializable) => modes.push("ISOLATION LEVEL SERIALIZABLE"),
+            None => {},
+        }
+        if self.read_only {
+            modes.push("READ ONLY");
+        }
+        if self.deferrable {
+            modes.push("DEFERRABLE");
+        }
+        modes
+    }
+
+    #[inline]
+    pub(crate) fn max_retries(&self) -> usize {
+        self.retries
+    }
+
+    // Runs `f` in a transaction on `conn`, committing if it returns `Ok`
+    // and rolling back otherwise; see `savepoint` to nest in a transaction.
+    pub fn run<T, E, F>(&self, conn: &postgres::Connection, mut f: F) -> Result<T, E>
+    where E: TransactionError + From<PgError>, F: FnMut(&Transaction) -> Result<T, E> {
+        let modes = self.set_transaction();
+        retrying(self.retries, is_retryable::<E>, || {
+            run_once(conn.transaction()?, &modes, &mut f)
+        })
+    }
+
+    fn set_transaction(&self) -> String {
+        let modes = self.modes();
+        if modes.is_empty() {
+            String::new()
+        } else {
+            format!("SET TRANSACTION {}", modes.join(", "))
+        }
+    }
+}
+
+// Reruns `attempt` while it fails with an error `retryable` accepts, at
+// most `retries` more times.
+fn retrying<T, E, R, F>(retries: usize, retryable: R, mut attempt: F) -> Result<T, E>
+where R: Fn(&E) -> bool, F: FnMut() -> Result<T, E> {
+    let mut retried = 0;
+    loop {
+        match attempt() {
+            Err(ref e) if retried < retries && retryable(e) => retried += 1,
+            result => return result,
+        }
+    }
+}
+
+fn run_once<T, E, F>(tx: Transaction, modes: &str, f: &mut F) -> Result<T, E>
+where E: TransactionError + From<PgError>, F: FnMut(&Transaction) -> Result<T, E> {
+    if !modes.is_empty() {
+        tx.batch_execute(modes)?;
+    }
+    // dropping `tx` without committing rolls back
+    let value = f(&tx)?;
+    tx.commit()?;
+    Ok(value)
+}
+
+// Runs `f` in a savepoint of `tx`, releasing it if `f` returns `Ok` and
+// rolling back to it otherwise.  It's never retried: a serialization
+// failure or deadlock aborts the whole transaction, so the error goes up
+// to the top level `run`.
+pub fn savepoint<T, E, F>(tx: &Transaction, mut f: F) -> Result<T, E>
+where E: TransactionError + From<PgError>, F: FnMut(&Transaction) -> Result<T, E> {
+    run_once(tx.transaction()?, "", &mut f)
+}
+
+// `TransactionOptions::default().run(conn, f)`
+pub fn transaction<T, E, F>(conn: &postgres::Connection, f: F) -> Result<T, E>
+where E: TransactionError + From<PgError>, F: FnMut(&Transaction) -> Result<T, E> {
+    TransactionOptions::default().run(conn, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs `retrying` over `results`, returning its result and how many
+    // attempts it made.
+    fn attempts(retries: usize, results: Vec<Result<u8, &'static str>>)
+    -> (Result<u8, &'static str>, usize) {
+        let mut results = results.into_iter();
+        let mut made = 0;
+        let result = retrying(retries, |e: &&str| *e == "retry", || {
+            made += 1;
+            results.next().unwrap()
+        });
+        (result, made)
+    }
+
+    #[test]
+    fn retries_until_ok() {
+        assert_eq!(attempts(3, vec![Err("retry"), Err("retry"), Ok(1)]), (Ok(1), 3));
+        assert_eq!(attempts(0, vec![Ok(1)]), (Ok(1), 1));
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        assert_eq!(attempts(2, vec![Err("retry"); 3]), (Err("retry"), 3));
+        assert_eq!(attempts(0, vec![Err("retry")]), (Err("retry"), 1));
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        assert_eq!(attempts(3, vec![Err("retry"), Err("fail"), Ok(1)]), (Err("fail"), 2));
+    }
+
+    #[test]
+    fn sets_transaction_modes() {
+        assert_eq!(TransactionOptions::new().set_transaction(), "");
+        let options = TransactionOptions::new()
+            .isolation(IsolationLevel::Serializable)
+            .read_only(true)
+            .deferrable(true);
+        assert_eq!(options.set_transaction(),
+                   "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE");
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use crate::{*, utils::*, source::Typed};
+use crate::query::synchronous::Connection;
+use postgres::{Error, types::{FromSql, ToSql, Type}};
+
+// Compares declared (typed) columns with a live database: the columns
+// must exist, agree on nullability, and hold a postgres type that the
+// rust type of the column can be written as and read from.
+
+pub struct Declared {
+    pub name: String,
+    pub sql_type: &'static str,
+    pub nullable: bool,
+    accepts: fn(&Type) -> bool,
+}
+
+fn accepts<T: ToSql + for<'a> FromSql<'a>>(ty: &Type) -> bool {
+    <T as ToSql>::accepts(ty) && <T as FromSql>::accepts(ty)
+}
+
+pub trait DeclaredColumns<F: Source> {
+    fn push_declared(&self, out: &mut Vec<Declared>);
+}
+
+impl<F: Source, C: Column<F> + Typed> DeclaredColumns<F> for ColWrap<C>
+where C::Type: ToSql + for<'a> FromSql<'a> {
+    fn push_declared(&self, out: &mut Vec<Declared>) {
+        let mut name = String::new();
+        self.0.push_name(&mut name);
+        out.push(Declared {
+            name,
+            sql_type: C::SQL_TYPE,
+            nullable: C::NULLABLE,
+            accepts: accepts::<C::Type>,
+        });
+    }
+}
+
+impl<F: Source, A: DeclaredColumns<F>, B: DeclaredColumns<F>> DeclaredColumns<F> for Seq<A, B> {
+    fn push_declared(&self, out: &mut Vec<Declared>) {
+        self.0.push_declared(out);
+        self.1.push_declared(out);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Drift {
+    MissingTable { table: String },
+    MissingColumn { table: String, column: String },
+    Type { table: String, column: String, declared: &'static str, found: String },
+    // `declared` is whether the column was declared nullable
+    Nullability { table: String, column: String, declared: bool },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Drift::MissingTable { ref table } =>
+                write!(f, "table {} is missing", table),
+            Drift::MissingColumn { ref table, ref column } =>
+                write!(f, "column {}.{} is missing", table, column),
+            Drift::Type { ref table, ref column, declared, ref found } =>
+                write!(f, "column {}.{} is declared {} but is {}", table, column, declared, found),
+            Drift::Nullability { ref table, ref column, declared: true } =>
+                write!(f, "column {}.{} is declared nullable but is not null", table, column),
+            Drift::Nullability { ref table, ref column, declared: false } =>
+                write!(f, "column {}.{} is declared not null but is nullable", table, column),
+        }
+    }
+}
+
+fn unquote(ident: &str) -> String {
+    if ident.len() >= 2 && ident.starts_with('"') && ident.ends_with('"') {
+        ident[1..ident.len() - 1].replace("\"\"", "\"")
+    } else {
+        ident.to_string()
+    }
+}
+
+// Checks `columns` of `source`; eg. `check(conn, &users::Table, &users::ALL)`.
+pub fn check<F: Source, C: DeclaredColumns<F>>(conn: &dyn Connection, source: &F, columns: &C)
+-> Result<Vec<Drift>, Error> {
+    let mut table = String::new();
+    source.push_source(&mut table);
+    let (schema, name) = match table.rfind('.') {
+        Some(dot) => (Some(unquote(&table[..dot])), unquote(&table[dot + 1..])),
+        None => (None, unquote(&table)),
+    };
+
+    let rows = conn.query(
+        "SELECT column_name::text, is_nullable::text = 'YES'
+         FROM information_schema.columns
+         WHERE table_schema = coalesce($1, current_schema()) AND table_name = $2",
+        &[&schema, &name])?;
+    if rows.is_empty() {
+        return Ok(vec![Drift::MissingTable { table }]);
+    }
+    let found: Vec<(String, bool)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+
+    let mut declared = vec![];
+    columns.push_declared(&mut declared);
+
+    let mut drifts = vec![];
+    let mut present = vec![];
+    for column in declared {
+        match found.iter().find(|f| f.0 == unquote(&column.name)) {
+            None => drifts.push(Drift::MissingColumn {
+                table: table.clone(), column: column.name,
+            }),
+            Some(&(_, nullable)) => {
+                if nullable != column.nullable {
+                    drifts.push(Drift::Nullability {
+                        table: table.clone(),
+                        column: column.name.clone(),
+                        declared: column.nullable,
+                    });
+                }
+                present.push(column);
+            },
+        }
+    }
+    if present.is_empty() {
+        return Ok(drifts);
+    }
+
+    // the types as the driver sees them
+    let names: Vec<&str> = present.iter().map(|c| &c.name[..]).collect();
+    let stmt = conn.prepare(&format!("SELECT {} FROM {} LIMIT 0", names.join(", "), table))?;
+    for (column, info) in present.iter().zip(stmt.columns()) {
+        if !(column.accepts)(info.type_()) {
+            drifts.push(Drift::Type {
+                table: table.clone(),
+                column: column.name.clone(),
+                declared: column.sql_type,
+                found: info.type_().name().to_string(),
+            });
+        }
+    }
+    Ok(drifts)
+}
@@ -1,9 +1,33 @@
 use crate::{*, utils::*};
 use postgres::types::ToSql;
+use std::marker::PhantomData;
 
-pub trait ColumnsSetter<F: Source> {
+pub trait ColumnsSetter<F: Source>: Sized {
+    type Set;
     fn push_selection(&self, buf: &mut String) -> bool;
     fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool);
+    fn into_types(self) -> Self::Set;
+
+    // Whether the setter can be rendered as built.
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        if !self.push_selection(buf) {
+            return (idx, false);
+        }
+        buf.push_str(" = ");
+        self.push_values(buf, idx)
+    }
+
+    #[inline]
+    fn push_shaped_values(&self, buf: &mut String, idx: usize,
+                          _shape: &mut dyn Iterator<Item = bool>) -> (usize, bool) {
+        self.push_values(buf, idx)
+    }
 }
 
 impl<
@@ -18,16 +42,52 @@ impl<
         self.1.push_selection(buf)
     }
 
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()?;
+        self.1.check()
+    }
+
     #[inline]
     fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
         let (idx, did) = self.0.push_values(buf, idx);
         if did { buf.push_str(", "); }
         self.1.push_values(buf, idx)
     }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        let (idx, did_first) = self.0.push_assignments(buf, idx);
+        let len = buf.len();
+        if did_first { buf.push_str(", "); }
+        let (idx, did_second) = self.1.push_assignments(buf, idx);
+        if did_first && !did_second { buf.truncate(len); }
+        (idx, did_first || did_second)
+    }
+
+    #[inline]
+    fn push_shaped_values(&self, buf: &mut String, idx: usize,
+                          shape: &mut dyn Iterator<Item = bool>) -> (usize, bool) {
+        let (idx, did) = self.0.push_shaped_values(buf, idx, shape);
+        if did { buf.push_str(", "); }
+        self.1.push_shaped_values(buf, idx, shape)
+    }
+
+    type Set = Seq![A::Set, B::Set];
+    fn into_types(self) -> Self::Set {
+        seq![self.0.into_types(), self.1.into_types()]
+    }
 }
 
 pub trait Takes<'a, S> {
     fn push_values<'b:'a>(&'b self, values: S, buf: &mut Vec<&'a ToSql>);
+
+    // Whether the values fit the statement as prepared; checked before
+    // they're pushed.
+    #[inline]
+    fn check_values(&self, _values: &S) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 impl<'a, S, T: Takes<'a, S>> Takes<'a, Wrap<S>> for Wrap<T> {
@@ -35,6 +95,11 @@ impl<'a, S, T: Takes<'a, S>> Takes<'a, Wrap<S>> for Wrap<T> {
     fn push_values<'b:'a>(&'b self, values: Wrap<S>, buf: &mut Vec<&'a ToSql>) {
         self.0.push_values(values.0, buf);
     }
+
+    #[inline]
+    fn check_values(&self, values: &Wrap<S>) -> Result<(), Error> {
+        self.0.check_values(&values.0)
+    }
 }
 
 impl<'a, S, T: Takes<'a, S>> Takes<'a, S> for ColWrap<T> {
@@ -111,12 +176,115 @@ impl<
         self.0.push_selection(buf)
     }
 
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
     #[inline]
     fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
         <S as ColumnsSetter<F>>::push_values(
             &self.0, buf, idx
         )
     }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        <S as ColumnsSetter<F>>::push_assignments(
+            &self.0, buf, idx
+        )
+    }
+
+    type Set = WithValue<S::Set, A>;
+    fn into_types(self) -> Self::Set {
+        WithValue(self.0.into_types(), self.1)
+    }
+}
+
+// A bare parameter of type `T`, for sql that isn't tied to a column.
+pub struct Param<T>(PhantomData<T>);
+
+#[inline]
+pub fn param<T>() -> Param<T> {
+    Param(PhantomData)
+}
+
+impl<'a, T: ToSql + 'a> Takes<'a, &'a T> for Param<T> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, values: &'a T, buf: &mut Vec<&'a ToSql>) {
+        buf.push(values);
+    }
+}
+
+// The number of bare parameters a setter takes; see `Function`.
+pub trait Params {
+    fn count(&self) -> usize;
+}
+
+impl<T> Params for Param<T> {
+    #[inline]
+    fn count(&self) -> usize {
+        1
+    }
+}
+
+impl Params for Unit {
+    #[inline]
+    fn count(&self) -> usize {
+        0
+    }
+}
+
+impl<A: Params, B: Params> Params for Seq<A, B> {
+    #[inline]
+    fn count(&self) -> usize {
+        self.0.count() + self.1.count()
+    }
+}
+
+// Like `WithValue`, but borrowing the value.
+pub struct RefValue<'r, S, A>(pub S, pub &'r A);
+
+impl<'a, 'r: 'a, S, A> Takes<'a, Unit> for RefValue<'r, S, A>
+where S: Takes<'a, &'a A> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: Unit, buf: &mut Vec<&'a ToSql>) {
+        self.0.push_values(self.1, buf);
+    }
+}
+
+impl<
+    'r, F: Source, A,
+    S: ColumnsSetter<F>,
+> ColumnsSetter<F> for RefValue<'r, S, A> {
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        <S as ColumnsSetter<F>>::push_values(
+            &self.0, buf, idx
+        )
+    }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        <S as ColumnsSetter<F>>::push_assignments(
+            &self.0, buf, idx
+        )
+    }
+
+    type Set = RefValue<'r, S::Set, A>;
+    fn into_types(self) -> Self::Set {
+        RefValue(self.0.into_types(), self.1)
+    }
 }
 
 pub struct OptValue<S, A>(pub S, pub A);
@@ -133,6 +301,11 @@ impl<
         }
     }
 
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
     #[inline]
     fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
         if let Some(_) = self.1 {
@@ -143,6 +316,22 @@ impl<
             (idx, false)
         }
     }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        if let Some(_) = self.1 {
+            <S as ColumnsSetter<F>>::push_assignments(
+                &self.0, buf, idx
+            )
+        } else {
+            (idx, false)
+        }
+    }
+
+    type Set = OptValue<S::Set, Option<A>>;
+    fn into_types(self) -> Self::Set {
+        OptValue(self.0.into_types(), self.1)
+    }
 }
 
 impl<
@@ -157,6 +346,11 @@ impl<
         }
     }
 
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
     #[inline]
     fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
         if let Some(_) = self.1 {
@@ -167,6 +361,22 @@ impl<
             (idx, false)
         }
     }
+
+    #[inline]
+    fn push_assignments(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        if let Some(_) = self.1 {
+            <S as ColumnsSetter<F>>::push_assignments(
+                &self.0, buf, idx
+            )
+        } else {
+            (idx, false)
+        }
+    }
+
+    type Set = OptValue<S::Set, &'c Option<A>>;
+    fn into_types(self) -> Self::Set {
+        OptValue(self.0.into_types(), self.1)
+    }
 }
 
 impl<'a, S, A: 'a> Takes<'a, Unit> for OptValue<S, Option<A>>
@@ -196,6 +406,12 @@ impl<C> ColWrap<C> {
         WithValue(self, assignment)
     }
 
+    #[inline]
+    pub fn taking_ref<'a, A: 'a>(self, assignment: &'a A) -> RefValue<'a, Self, A>
+    where Self: Takes<'a, &'a A> {
+        RefValue(self, assignment)
+    }
+
     #[inline]
     pub fn if_some<'a, A: 'a>(self, assignment: Option<A>) -> OptValue<Self, Option<A>>
     where Self: Takes<'a, &'a A> {
@@ -207,6 +423,251 @@ impl<C> ColWrap<C> {
     where Self: Takes<'a, &'a A> {
         OptValue(self, assignment)
     }
+
+    #[inline]
+    pub fn or_default(self) -> OrDefault<Self> {
+        OrDefault(self)
+    }
+
+    #[inline]
+    pub fn default_value(self) -> SetDefault<Self> {
+        SetDefault(self)
+    }
+
+    #[inline]
+    pub fn raw_value(self, sql: &'static str) -> SetRaw<Self> {
+        SetRaw(self, sql)
+    }
+
+    #[inline]
+    pub fn expr_value(self, sql: &'static str) -> SetExpr<Self> {
+        SetExpr(self, sql, sql.matches("{}").count())
+    }
+
+    #[inline]
+    pub fn query_value<S, Q: IntoSql<Get = Wrap<S>>>(self, query: Q) -> SetQuery<Self, Q> {
+        SetQuery(self, query)
+    }
+}
+
+// `col = DEFAULT`
+pub struct SetDefault<S>(pub S);
+
+impl<F: Source, S: ColumnsSetter<F>> ColumnsSetter<F> for SetDefault<S> {
+    type Set = Self;
+
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        buf.push_str("DEFAULT");
+        (idx, true)
+    }
+
+    fn into_types(self) -> Self::Set {
+        self
+    }
+}
+
+impl<'a, S> Takes<'a, Unit> for SetDefault<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: Unit, _buf: &mut Vec<&'a ToSql>) {}
+}
+
+impl<'a, S> Takes<'a, &'a Unit> for SetDefault<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: &'a Unit, _buf: &mut Vec<&'a ToSql>) {}
+}
+
+// `col = <sql>`, where the sql takes no parameters; eg. `now()`.
+pub struct SetRaw<S>(pub S, pub &'static str);
+
+impl<F: Source, S: ColumnsSetter<F>> ColumnsSetter<F> for SetRaw<S> {
+    type Set = Self;
+
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        buf.push_str(self.1);
+        (idx, true)
+    }
+
+    fn into_types(self) -> Self::Set {
+        self
+    }
+}
+
+impl<'a, S> Takes<'a, Unit> for SetRaw<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, _values: Unit, _buf: &mut Vec<&'a ToSql>) {}
+}
+
+// `col = <sql>`, where the `{}` in the sql is replaced by the parameter
+// taken by the column; eg. `counter + {}`.  The sql must hold exactly
+// one `{}` (see `SetRaw` for sql taking none); the count is kept from
+// `expr_value` and checked when preparing.
+pub struct SetExpr<S>(pub S, pub &'static str, usize);
+
+impl<S> SetExpr<S> {
+    #[inline]
+    pub fn taking<'a, A: 'a>(self, assignment: A) -> WithValue<Self, A>
+    where Self: Takes<'a, &'a A> {
+        WithValue(self, assignment)
+    }
+}
+
+impl<F: Source, S: ColumnsSetter<F>> ColumnsSetter<F> for SetExpr<S> {
+    type Set = Self;
+
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        if self.2 != 1 {
+            return Err(Error::PlaceholderCount { sql: self.1, expected: 1, found: self.2 });
+        }
+        self.0.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        buf.push_str(&self.1.replace("{}", &format!("${}", idx)));
+        (if self.2 == 0 { idx } else { idx + 1 }, true)
+    }
+
+    fn into_types(self) -> Self::Set {
+        self
+    }
+}
+
+impl<'a, V, S: Takes<'a, V>> Takes<'a, V> for SetExpr<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, values: V, buf: &mut Vec<&'a ToSql>) {
+        self.0.push_values(values, buf);
+    }
+}
+
+// `col = (<sub-query>)`
+pub struct SetQuery<S, Q>(pub S, pub Q);
+
+impl<F: Source, S: ColumnsSetter<F>, G, Q: IntoSql<Get = Wrap<G>>>
+ColumnsSetter<F> for SetQuery<S, Q> {
+    type Set = Q::Set;
+
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()?;
+        self.1.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        buf.push_str("(");
+        let idx = self.1.push_sql(buf, idx);
+        buf.push_str(")");
+        (idx, true)
+    }
+
+    fn into_types(self) -> Self::Set {
+        self.1.into_types().1
+    }
+}
+
+// Takes an `Option` per row; `None` renders `DEFAULT` in place of the
+// placeholder when the insert is repeated over a `Shape`.
+pub struct OrDefault<S>(pub S);
+
+impl<F: Source, S: ColumnsSetter<F>> ColumnsSetter<F> for OrDefault<S> {
+    type Set = OrDefault<S::Set>;
+
+    #[inline]
+    fn push_selection(&self, buf: &mut String) -> bool {
+        self.0.push_selection(buf)
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()
+    }
+
+    #[inline]
+    fn push_values(&self, buf: &mut String, idx: usize) -> (usize, bool) {
+        self.0.push_values(buf, idx)
+    }
+
+    #[inline]
+    fn push_shaped_values(&self, buf: &mut String, idx: usize,
+                          shape: &mut dyn Iterator<Item = bool>) -> (usize, bool) {
+        match shape.next() {
+            Some(false) => {
+                buf.push_str("DEFAULT");
+                (idx, true)
+            },
+            _ => self.0.push_values(buf, idx),
+        }
+    }
+
+    fn into_types(self) -> Self::Set {
+        OrDefault(self.0.into_types())
+    }
+}
+
+impl<'a, A, S: Takes<'a, A>> Takes<'a, Option<A>> for OrDefault<S> {
+    #[inline]
+    fn push_values<'b:'a>(&'b self, values: Option<A>, buf: &mut Vec<&'a ToSql>) {
+        if let Some(values) = values {
+            self.0.push_values(values, buf);
+        }
+    }
+}
+
+pub trait Shapes<A> {
+    fn push_shape(&self, values: &A, shape: &mut Vec<bool>);
+}
+
+impl<C, A> Shapes<A> for ColWrap<C> {
+    #[inline]
+    fn push_shape(&self, _values: &A, _shape: &mut Vec<bool>) {}
+}
+
+impl<S, A> Shapes<Option<A>> for OrDefault<S> {
+    #[inline]
+    fn push_shape(&self, values: &Option<A>, shape: &mut Vec<bool>) {
+        shape.push(values.is_some());
+    }
+}
+
+impl<A, B, S: Shapes<A>, T: Shapes<B>> Shapes<Seq<A, B>> for Seq<S, T> {
+    #[inline]
+    fn push_shape(&self, values: &Seq<A, B>, shape: &mut Vec<bool>) {
+        self.0.push_shape(&values.0, shape);
+        self.1.push_shape(&values.1, shape);
+    }
 }
 
 pub trait RefSetter<'a> {
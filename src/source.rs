@@ -1,9 +1,25 @@
 use crate::{*, utils::*};
 
 pub trait Source: Sized {
+    type Set;
+
     #[inline]
     fn push_source(&self, buf: &mut String);
 
+    #[inline]
+    fn push_from(&self, buf: &mut String, idx: usize) -> usize {
+        self.push_source(buf);
+        idx
+    }
+
+    fn into_set(self) -> Self::Set;
+
+    // Whether the source can be rendered as built.
+    #[inline]
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
     #[inline]
     fn select(self) -> SelectBuilder<Self, Unit, Unit, Unit, Unit, Unit, Unit> {
         SelectBuilder {
@@ -47,24 +63,114 @@ pub trait Source: Sized {
             where_clause: Unit,
         }
     }
+
+    #[inline]
+    fn copy_in(self) -> CopyInBuilder<Self, Unit> {
+        CopyInBuilder {
+            source: self,
+            values: Unit,
+        }
+    }
+
+    #[inline]
+    fn create_table(self) -> CreateTableBuilder<Self, Unit, Unit, Unit> {
+        CreateTableBuilder {
+            source: self,
+            columns: Unit,
+            primary_key: Unit,
+            if_not_exists: Unit,
+        }
+    }
+
+    #[inline]
+    fn alter_table(self) -> AlterTableBuilder<Self, Unit> {
+        AlterTableBuilder {
+            source: self,
+            actions: Unit,
+        }
+    }
+
+    #[inline]
+    fn create_index(self, name: &'static str)
+    -> CreateIndexBuilder<Self, &'static str, Unit, Unit, Unit, Unit> {
+        CreateIndexBuilder {
+            source: self,
+            name,
+            keys: Unit,
+            where_clause: Unit,
+            unique: Unit,
+            if_not_exists: Unit,
+        }
+    }
+
+    #[inline]
+    fn drop_table(self) -> DropTableBuilder<Self, Unit, Unit> {
+        DropTableBuilder {
+            source: self,
+            if_exists: Unit,
+            cascade: Unit,
+        }
+    }
+
+    #[inline]
+    fn calling<S>(self, sql: &'static str, setter: S) -> Function<Self, S> {
+        Function(self, sql, setter)
+    }
+
+    #[inline]
+    fn lateral<T: Source, G, Q: IntoSql<Get = Wrap<G>>>(self, alias: T, query: Q)
+    -> Lateral<Self, T, Q> {
+        Lateral(self, alias, query)
+    }
 }
 
 pub trait Column<F: Source> {
     #[inline]
     fn push_name(&self, buf: &mut String);
+
+    // The name as selected from `src`.
+    #[inline]
+    fn push_qualified(&self, src: &F, buf: &mut String) {
+        src.push_source(buf);
+        buf.push_str(".");
+        self.push_name(buf);
+    }
+}
+
+// Sources named by their type alone, as declared with `table!`; lets
+// their columns be qualified where no instance is at hand.
+pub trait Named: Source {
+    const NAME: &'static str;
+}
+
+// The value type of a column declared with `column!(.., $ty)`, and the
+// name of its sql type.
+pub trait Typed {
+    type Type;
+    const SQL_TYPE: &'static str;
+    const NULLABLE: bool;
+}
+
+// Columns that may hold nulls; typed ones decode only into `Option`s.
+pub trait Nullable {}
+
+// The primary key columns of a table; see `derive(Table)`.
+pub trait PrimaryKey: Source {
+    type Key: Selection<Self>;
+    fn primary_key() -> Self::Key;
 }
 
 impl<F: Source, C: Column<F>> Selection<F> for ColWrap<C> {
     #[inline]
     fn push_selection(&self, src: &F, buf: &mut String) -> bool {
-        src.push_source(buf);
-        buf.push_str(".");
-        self.0.push_name(buf);
+        self.0.push_qualified(src, buf);
         true
     }
 }
 
 impl<F: Source, C: Column<F>> ColumnsSetter<F> for ColWrap<C> {
+    type Set = Self;
+
     #[inline]
     fn push_selection(&self, buf: &mut String) -> bool {
         self.0.push_name(buf);
@@ -76,5 +182,143 @@ impl<F: Source, C: Column<F>> ColumnsSetter<F> for ColWrap<C> {
         buf.push_str(&format!("${}", idx));
         (idx + 1, true)
     }
+
+    fn into_types(self) -> Self::Set {
+        self
+    }
+}
+
+
+// A column qualified by its own source, usable from any `FROM`; eg. the
+// columns of a lateral sub-query, or of the outer query in a correlated
+// sub-query.
+pub struct Qualified<T, C>(pub T, pub C);
+
+impl<C> ColWrap<C> {
+    #[inline]
+    pub fn of<T: Source>(self, src: T) -> Qualified<T, C>
+    where C: Column<T> {
+        Qualified(src, self.0)
+    }
+}
+
+impl<F: Source, T: Source, C: Column<T>> Selection<F> for Qualified<T, C> {
+    #[inline]
+    fn push_selection(&self, _src: &F, buf: &mut String) -> bool {
+        self.0.push_source(buf);
+        buf.push_str(".");
+        self.1.push_name(buf);
+        true
+    }
+}
+
+impl<'a, T, S, A: Makes<'a, S>> Makes<'a, Qualified<T, S>> for A {
+    fn get<R: Row>(s: &'a Qualified<T, S>, row: &'a R, idx: usize) -> (Self, usize) {
+        Makes::get(&s.1, row, idx)
+    }
+}
+
+// A set-returning function named by the source `T`; every `{}` in the
+// sql is replaced by the next placeholder, to be taken by `S`.  Eg.
+// `Ids.calling("unnest({}::int4[])", param::<Vec<i32>>())`.
+pub struct Function<T, S>(T, &'static str, S);
+
+impl<T: Source, S: Params> Source for Function<T, S> {
+    type Set = S;
+
+    #[inline]
+    fn push_source(&self, buf: &mut String) {
+        self.0.push_source(buf);
+    }
+
+    fn push_from(&self, buf: &mut String, mut idx: usize) -> usize {
+        let mut parts = self.1.split("{}");
+        if let Some(first) = parts.next() {
+            buf.push_str(first);
+        }
+        for part in parts {
+            buf.push_str(&format!("${}", idx));
+            buf.push_str(part);
+            idx += 1;
+        }
+        buf.push_str(" AS ");
+        self.0.push_source(buf);
+        idx
+    }
+
+    fn into_set(self) -> Self::Set {
+        self.2
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let found = self.1.matches("{}").count();
+        let expected = self.2.count();
+        if found != expected {
+            return Err(Error::PlaceholderCount { sql: self.1, expected, found });
+        }
+        Ok(())
+    }
+}
+
+impl<T: Named, S: Params> Named for Function<T, S> {
+    const NAME: &'static str = T::NAME;
+}
+
+impl<T: Source, S: Params, C: Column<T>> Column<Function<T, S>> for C {
+    #[inline]
+    fn push_name(&self, buf: &mut String) {
+        <C as Column<T>>::push_name(self, buf);
+    }
 }
 
+// `FROM <source>, LATERAL (<query>) AS <alias>`; columns of the outer
+// source are qualified by its name, while those of the alias are
+// `Qualified`.
+pub struct Lateral<F, T, Q>(F, T, Q);
+
+impl<F: Source, T: Source, G, Q: IntoSql<Get = Wrap<G>>> Source for Lateral<F, T, Q> {
+    type Set = Seq<F::Set, Q::Set>;
+
+    #[inline]
+    fn push_source(&self, buf: &mut String) {
+        self.0.push_source(buf);
+    }
+
+    fn push_from(&self, buf: &mut String, idx: usize) -> usize {
+        let idx = self.0.push_from(buf, idx);
+        buf.push_str(", LATERAL (");
+        let idx = self.2.push_sql(buf, idx);
+        buf.push_str(") AS ");
+        self.1.push_source(buf);
+        idx
+    }
+
+    fn into_set(self) -> Self::Set {
+        Seq(self.0.into_set(), self.2.into_types().1)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        self.0.check()?;
+        self.2.check()
+    }
+}
+
+impl<F: Named, T, Q> Named for Lateral<F, T, Q>
+where Lateral<F, T, Q>: Source {
+    const NAME: &'static str = F::NAME;
+}
+
+impl<F: Named, T, Q, C: Column<F>> Column<Lateral<F, T, Q>> for C
+where Lateral<F, T, Q>: Source {
+    #[inline]
+    fn push_name(&self, buf: &mut String) {
+        buf.push_str(F::NAME);
+        buf.push_str(".");
+        <C as Column<F>>::push_name(self, buf);
+    }
+
+    #[inline]
+    fn push_qualified(&self, _src: &Lateral<F, T, Q>, buf: &mut String) {
+        self.push_name(buf);
+    }
+}
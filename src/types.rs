@@ -0,0 +1,37 @@
+pub trait SqlType {
+    const NAME: &'static str;
+}
+
+impl<T: SqlType> SqlType for Option<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+macro_rules! sql_types {
+    ($($ty:ty => $name:expr),* $(,)*) => {
+        $(
+            impl SqlType for $ty {
+                const NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+
+sql_types! {
+    bool => "bool",
+    i8 => "\"char\"",
+    i16 => "int2",
+    i32 => "int4",
+    u32 => "oid",
+    i64 => "int8",
+    f32 => "float4",
+    f64 => "float8",
+    String => "text",
+    Vec<u8> => "bytea",
+    Vec<bool> => "bool[]",
+    Vec<i16> => "int2[]",
+    Vec<i32> => "int4[]",
+    Vec<i64> => "int8[]",
+    Vec<f32> => "float4[]",
+    Vec<f64> => "float8[]",
+    Vec<String> => "text[]",
+}
@@ -0,0 +1,129 @@
+#[macro_use]
+extern crate tygres;
+
+use postgres::types::ToSql;
+use tygres::*;
+use tygres::utils::Unit;
+
+mod users {
+    table!(Users, "users" => ALL {
+        (Id, ID, "id", i32),
+        (Name, NAME, "name", String),
+        (Email, EMAIL, "email", nullable String),
+    });
+}
+
+use users::Users;
+
+#[derive(Getter, HasSetter, HasOwnedSetter)]
+#[tygres(table = "users::Users", columns = "users")]
+struct User {
+    id: i32,
+    name: String,
+    email: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(HasSetter, HasOwnedSetter)]
+#[tygres(table = "users::Users", columns = "users")]
+struct NewUser {
+    #[tygres(default)]
+    id: i32,
+    name: String,
+    email: Option<String>,
+    #[tygres(skip)]
+    note: String,
+}
+
+fn user(email: Option<&str>) -> User {
+    User {
+        id: 1,
+        name: "name".to_string(),
+        email: email.map(|e| e.to_string()),
+    }
+}
+
+fn count<'a, S: Takes<'a, Unit>>(setter: &'a S) -> usize {
+    let mut buf: Vec<&'a ToSql> = vec![];
+    setter.push_values(Unit, &mut buf);
+    buf.len()
+}
+
+#[test]
+fn getter_selects_every_field() {
+    let mut sql = String::new();
+    Users.select().selecting(User::getter()).push_sql(&mut sql, 1);
+    assert_eq!(sql, "SELECT users.id, users.name, users.email FROM users");
+}
+
+#[test]
+fn ref_setter_skips_none() {
+    let (some, none) = (user(Some("e@x")), user(None));
+    assert_eq!(count(&RefSetter::as_setter(&some)), 3);
+    assert_eq!(count(&RefSetter::as_setter(&none)), 2);
+}
+
+#[test]
+fn val_setter_skips_none() {
+    assert_eq!(count(&ValSetter::as_setter(user(Some("e@x")))), 3);
+    assert_eq!(count(&ValSetter::as_setter(user(None))), 2);
+}
+
+#[test]
+fn has_setter_sets_nulls() {
+    let row = user(None);
+    let setter = <User as HasSetter<'_>>::setter();
+    let mut buf: Vec<&ToSql> = vec![];
+    setter.push_values(row.as_value(), &mut buf);
+    assert_eq!(buf.len(), 3);
+
+    let new = NewUser { id: 0, name: "name".to_string(), email: None, note: String::new() };
+    let (setter, value) = (<NewUser as HasOwnedSetter>::setter(), new.into_value());
+    let mut buf: Vec<&ToSql> = vec![];
+    setter.push_values(&value, &mut buf);
+    assert_eq!(buf.len(), 2);
+}
+
+#[allow(dead_code)]
+#[derive(Table)]
+#[tygres(name = "accounts")]
+struct Account {
+    #[tygres(primary_key, default)]
+    id: i32,
+    #[tygres(name = "e_mail")]
+    email: String,
+    nick: Option<String>,
+}
+
+#[test]
+fn table_declares_columns() {
+    let mut sql = String::new();
+    accounts::Table.select().selecting(accounts::ALL).push_sql(&mut sql, 1);
+    assert_eq!(sql, "SELECT accounts.id, accounts.e_mail, accounts.nick FROM accounts");
+    assert!(<accounts::Nick as tygres::Typed>::NULLABLE);
+}
+
+#[test]
+fn table_sets_option_fields() {
+    let account = Account { id: 0, email: "e@x".to_string(), nick: None };
+    assert_eq!(count(&RefSetter::as_setter(&account)), 1);
+    assert_eq!(count(&ValSetter::as_setter(account)), 1);
+
+    let account = Account { id: 0, email: "e@x".to_string(), nick: Some("n".to_string()) };
+    assert_eq!(count(&RefSetter::as_setter(&account)), 2);
+}
+
+#[allow(dead_code)]
+#[derive(Table)]
+#[tygres(name = "app.events", module = "events")]
+struct Event {
+    id: i32,
+    r#type: String,
+}
+
+#[test]
+fn table_takes_module_and_raw_fields() {
+    let mut sql = String::new();
+    events::Table.select().selecting(seq!(events::ID, events::TYPE)).push_sql(&mut sql, 1);
+    assert_eq!(sql, "SELECT app.events.id, app.events.type FROM app.events");
+}
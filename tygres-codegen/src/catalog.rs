@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use postgres::GenericConnection;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// What the generator needs to know of a schema.  It is read from a live
+// database, or from a snapshot previously saved with `Catalog::save`.
+
+#[derive(Debug, Default)]
+pub struct Catalog {
+    // the schema read, unless `public`; it qualifies the table names
+    pub schema: Option<String>,
+    pub tables: Vec<Table>,
+    pub enums: Vec<Enum>,
+}
+
+#[derive(Debug)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub primary_key: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Column {
+    pub name: String,
+    // `udt_name`; arrays are prefixed with an underscore
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+#[derive(Debug)]
+pub struct Enum {
+    pub name: String,
+    pub labels: Vec<String>,
+}
+
+const COLUMNS: &str = "
+    SELECT c.table_name::text, c.column_name::text, c.udt_name::text,
+           c.is_nullable::text = 'YES'
+    FROM information_schema.columns c
+    JOIN information_schema.tables t
+      ON t.table_schema = c.table_schema AND t.table_name = c.table_name
+    WHERE c.table_schema = $1 AND t.table_type = 'BASE TABLE'
+    ORDER BY c.table_name, c.ordinal_position";
+
+const PRIMARY_KEYS: &str = "
+    SELECT tc.table_name::text, kcu.column_name::text
+    FROM information_schema.table_constraints tc
+    JOIN information_schema.key_column_usage kcu
+      ON kcu.constraint_schema = tc.constraint_schema
+     AND kcu.constraint_name = tc.constraint_name
+     AND kcu.table_name = tc.table_name
+    WHERE tc.table_schema = $1 AND tc.constraint_type = 'PRIMARY KEY'
+    ORDER BY tc.table_name, kcu.ordinal_position";
+
+const ENUMS: &str = "
+    SELECT t.typname::text, e.enumlabel::text
+    FROM pg_catalog.pg_type t
+    JOIN pg_catalog.pg_enum e ON e.enumtypid = t.oid
+    JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace
+    WHERE n.nspname = $1
+    ORDER BY t.typname, e.enumsortorder";
+
+impl Catalog {
+    pub fn load<C: GenericConnection>(conn: &C, schema: &str) -> Result<Self> {
+        let mut catalog = Catalog::default();
+        if schema != "public" {
+            catalog.schema = Some(schema.to_string());
+        }
+
+        for row in &conn.query(COLUMNS, &[&schema])? {
+            let table: String = row.get(0);
+            let column = Column {
+                name: row.get(1),
+                sql_type: row.get(2),
+                nullable: row.get(3),
+            };
+            catalog.table_mut(table).columns.push(column);
+        }
+
+        for row in &conn.query(PRIMARY_KEYS, &[&schema])? {
+            let table: String = row.get(0);
+            catalog.table_mut(table).primary_key.push(row.get(1));
+        }
+
+        for row in &conn.query(ENUMS, &[&schema])? {
+            let name: String = row.get(0);
+            let label: String = row.get(1);
+            match catalog.enums.last_mut() {
+                Some(ref mut last) if last.name == name => last.labels.push(label),
+                _ => catalog.enums.push(Enum { name, labels: vec![label] }),
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    fn table_mut(&mut self, name: String) -> &mut Table {
+        match self.tables.iter().position(|t| t.name == name) {
+            Some(idx) => &mut self.tables[idx],
+            None => {
+                self.tables.push(Table { name, columns: vec![], primary_key: vec![] });
+                self.tables.last_mut().unwrap()
+            },
+        }
+    }
+
+    // The snapshot is line based, with tab separated (and escaped) fields:
+    //
+    //     schema  <name>
+    //     enum    <name>  <label>...
+    //     table   <name>
+    //     column  <name>  <udt name>  null|not null
+    //     key     <column>...
+    //
+    // `column` and `key` lines belong to the preceding `table`.
+    pub fn save<W: Write>(&self, out: &mut W) -> Result<()> {
+        if let Some(ref schema) = self.schema {
+            write_line(out, &["schema", schema])?;
+        }
+        for e in &self.enums {
+            let mut fields = vec!["enum", &e.name];
+            fields.extend(e.labels.iter().map(|l| &l[..]));
+            write_line(out, &fields)?;
+        }
+        for t in &self.tables {
+            write_line(out, &["table", &t.name])?;
+            for c in &t.columns {
+                let null = if c.nullable { "null" } else { "not null" };
+                write_line(out, &["column", &c.name, &c.sql_type, null])?;
+            }
+            if !t.primary_key.is_empty() {
+                let mut fields = vec!["key"];
+                fields.extend(t.primary_key.iter().map(|k| &k[..]));
+                write_line(out, &fields)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(input: R) -> Result<Self> {
+        let mut catalog = Catalog::default();
+        for (num, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            let bad = || format!("snapshot line {}: malformed `{}`", num + 1, line);
+            match (&fields[0][..], catalog.tables.last_mut()) {
+                ("schema", _) if fields.len() == 2 => catalog.schema = Some(fields[1].clone()),
+                ("enum", _) if fields.len() >= 2 => catalog.enums.push(Enum {
+                    name: fields[1].clone(),
+                    labels: fields[2..].to_vec(),
+                }),
+                ("table", _) if fields.len() == 2 => catalog.tables.push(Table {
+                    name: fields[1].clone(),
+                    columns: vec![],
+                    primary_key: vec![],
+                }),
+                ("column", Some(table)) if fields.len() == 4 => table.columns.push(Column {
+                    name: fields[1].clone(),
+                    sql_type: fields[2].clone(),
+                    nullable: match &fields[3][..] {
+                        "null" => true,
+                        "not null" => false,
+                        _ => return Err(bad().into()),
+                    },
+                }),
+                ("key", Some(table)) => table.primary_key.extend_from_slice(&fields[1..]),
+                _ => return Err(bad().into()),
+            }
+        }
+        Ok(catalog)
+    }
+}
+
+fn write_line<W: Write>(out: &mut W, fields: &[&str]) -> Result<()> {
+    let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+    writeln!(out, "{}", fields.join("\t"))?;
+    Ok(())
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = "\
+# comments and blank lines are skipped
+
+schema\tapp
+enum\tmood\thappy\twith\\ttab
+table\tusers
+column\tid\tint4\tnot null
+column\tline\\nbreak\ttext\tnull
+key\tid
+table\tempty
+";
+
+    #[test]
+    fn snapshot_round_trip() {
+        let catalog = Catalog::read(SNAPSHOT.as_bytes()).unwrap();
+        assert_eq!(catalog.schema.as_ref().map(|s| &s[..]), Some("app"));
+        assert_eq!(catalog.enums[0].labels, vec!["happy", "with\ttab"]);
+        assert_eq!(catalog.tables[0].columns[1].name, "line\nbreak");
+        assert!(catalog.tables[0].columns[1].nullable);
+        assert_eq!(catalog.tables[0].primary_key, vec!["id"]);
+        assert!(catalog.tables[1].columns.is_empty());
+
+        let mut saved = vec![];
+        catalog.save(&mut saved).unwrap();
+        let expected: String = SNAPSHOT.lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| format!("{}\n", l))
+            .collect();
+        assert_eq!(String::from_utf8(saved).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(Catalog::read("column\tid\tint4\tnot null\n".as_bytes()).is_err());
+        assert!(Catalog::read("table\tusers\ncolumn\tid\tint4\tmaybe\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::fmt::Write;
+
+use crate::catalog::{Catalog, Column, Enum, Table};
+
+// Renders the catalog as rust: an enum (with its `ToSql` and `FromSql`)
+// per postgres enum, and a module per table declaring it with its
+// columns (and `ALL`) in one `table!`, and the `PrimaryKey`.  The output expects
+// the `postgres` and `tygres` macros in scope, as `takes_json!` does.
+pub fn generate(catalog: &Catalog) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by tygres-codegen; do not edit.\n");
+    for e in &catalog.enums {
+        out.push('\n');
+        generate_enum(&mut out, e);
+    }
+    for t in &catalog.tables {
+        out.push('\n');
+        generate_table(&mut out, t, catalog);
+    }
+    out
+}
+
+// The rust type for a column of the (udt) `sql_type`, if there is one,
+// as named inside a table module.
+pub fn rust_type(sql_type: &str, catalog: &Catalog) -> Option<String> {
+    if let Some(element) = sql_type.strip_prefix('_') {
+        return rust_type(element, catalog).map(|ty| format!("Vec<{}>", ty));
+    }
+    let ty = match sql_type {
+        "bool" => "bool",
+        "char" => "i8",
+        "int2" => "i16",
+        "int4" => "i32",
+        "oid" => "u32",
+        "int8" => "i64",
+        "float4" => "f32",
+        "float8" => "f64",
+        "text" | "varchar" | "bpchar" | "name" | "citext" => "String",
+        "bytea" => "Vec<u8>",
+        _ => {
+            return catalog.enums.iter()
+                .find(|e| e.name == sql_type)
+                .map(|e| format!("super::{}", camel_ident(&e.name)));
+        },
+    };
+    Some(ty.into())
+}
+
+fn sql_type_name(sql_type: &str) -> String {
+    if let Some(element) = sql_type.strip_prefix('_') {
+        return format!("{}[]", sql_type_name(element));
+    }
+    match sql_type {
+        "char" => "\"char\"".into(),
+        _ => sql_type.into(),
+    }
+}
+
+fn generate_enum(out: &mut String, e: &Enum) {
+    let name = camel_ident(&e.name);
+    let variants: Vec<String> = e.labels.iter().map(|l| camel_ident(l)).collect();
+
+    writeln!(out, "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]").unwrap();
+    writeln!(out, "pub enum {} {{", name).unwrap();
+    for v in &variants {
+        writeln!(out, "    {},", v).unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "impl postgres::types::ToSql for {} {{", name).unwrap();
+    writeln!(out, "    fn to_sql(&self, _ty: &postgres::types::Type, out: &mut Vec<u8>) \
+                   -> Result<postgres::types::IsNull, Box<std::error::Error + Sync + Send>> {{").unwrap();
+    writeln!(out, "        out.extend_from_slice(match *self {{").unwrap();
+    for (v, l) in variants.iter().zip(&e.labels) {
+        writeln!(out, "            {}::{} => {:?}.as_bytes(),", name, v, l).unwrap();
+    }
+    writeln!(out, "        }});").unwrap();
+    writeln!(out, "        Ok(postgres::types::IsNull::No)").unwrap();
+    writeln!(out, "    }}\n").unwrap();
+    writeln!(out, "    fn accepts(ty: &postgres::types::Type) -> bool {{").unwrap();
+    writeln!(out, "        ty.name() == {:?}", e.name).unwrap();
+    writeln!(out, "    }}\n").unwrap();
+    writeln!(out, "    to_sql_checked!();").unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "impl<'a> postgres::types::FromSql<'a> for {} {{", name).unwrap();
+    writeln!(out, "    fn from_sql(_ty: &postgres::types::Type, raw: &'a [u8]) \
+                   -> Result<{}, Box<std::error::Error + Sync + Send>> {{", name).unwrap();
+    writeln!(out, "        match std::str::from_utf8(raw)? {{").unwrap();
+    for (v, l) in variants.iter().zip(&e.labels) {
+        writeln!(out, "            {:?} => Ok({}::{}),", l, name, v).unwrap();
+    }
+    writeln!(out, "            other => Err(format!(\"unknown {} label: {{}}\", other).into()),",
+             e.name.escape_default()).unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}\n").unwrap();
+    writeln!(out, "    fn accepts(ty: &postgres::types::Type) -> bool {{").unwrap();
+    writeln!(out, "        ty.name() == {:?}", e.name).unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn generate_table(out: &mut String, t: &Table, catalog: &Catalog) {
+    writeln!(out, "pub mod {} {{", snake_ident(&t.name)).unwrap();
+    writeln!(out, "    use tygres::*;\n").unwrap();
+    let names: Vec<String> = t.columns.iter().map(column_ident).collect();
+    let table = match catalog.schema {
+        Some(ref schema) => format!("{}.{}", quote_ident(schema), quote_ident(&t.name)),
+        None => quote_ident(&t.name),
+    };
+    if names.is_empty() {
+        writeln!(out, "    table!(Table, {:?});", table).unwrap();
+    } else {
+        writeln!(out, "    table!(Table, {:?} => ALL {{", table).unwrap();
+    }
+    for (c, name) in t.columns.iter().zip(&names) {
+        // the constant can't share the name of the (unit struct) column
+        let caps = match caps_ident(&c.name) {
+            ref caps if caps == "ALL" || caps == name => format!("{}_COLUMN", caps),
+            caps => caps,
+        };
+        let column = quote_ident(&c.name);
+        match rust_type(&c.sql_type, catalog) {
+            Some(ty) => {
+                let nullable = if c.nullable { "nullable " } else { "" };
+                writeln!(out, "        ({}, {}, {:?}, {}{}, {:?}),",
+                         name, caps, column, nullable, ty, sql_type_name(&c.sql_type)).unwrap();
+            },
+            None => {
+                writeln!(out, "        // unmapped type `{}`", c.sql_type).unwrap();
+                writeln!(out, "        ({}, {}, {:?}),", name, caps, column).unwrap();
+            },
+        }
+    }
+    if !names.is_empty() {
+        writeln!(out, "    }});").unwrap();
+    }
+
+    let keys: Vec<&String> = t.primary_key.iter()
+        .filter_map(|k| t.columns.iter().position(|c| &c.name == k))
+        .map(|idx| &names[idx])
+        .collect();
+    if !keys.is_empty() {
+        let tys: Vec<String> = keys.iter()
+            .map(|k| format!("tygres::utils::ColWrap<{}>", k)).collect();
+        let vals: Vec<String> = keys.iter()
+            .map(|k| format!("tygres::utils::ColWrap({})", k)).collect();
+        writeln!(out).unwrap();
+        writeln!(out, "    impl tygres::PrimaryKey for Table {{").unwrap();
+        writeln!(out, "        type Key = Seq![{}];", tys.join(", ")).unwrap();
+        writeln!(out, "        fn primary_key() -> Self::Key {{").unwrap();
+        writeln!(out, "            seq![{}]", vals.join(", ")).unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "return", "self", "static", "struct",
+    "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "async", "await", "dyn", "abstract", "become", "box", "do", "final",
+    "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+];
+
+// Reserved in postgres, even as column names.
+const SQL_KEYWORDS: &[&str] = &[
+    "all", "analyse", "analyze", "and", "any", "array", "as", "asc",
+    "asymmetric", "authorization", "binary", "both", "case", "cast", "check",
+    "collate", "collation", "column", "concurrently", "constraint", "create",
+    "cross", "current_catalog", "current_date", "current_role",
+    "current_schema", "current_time", "current_timestamp", "current_user",
+    "default", "deferrable", "desc", "distinct", "do", "else", "end", "except",
+    "false", "fetch", "for", "foreign", "freeze", "from", "full", "grant",
+    "group", "having", "ilike", "in", "initially", "inner", "intersect",
+    "into", "is", "isnull", "join", "lateral", "leading", "left", "like",
+    "limit", "localtime", "localtimestamp", "natural", "not", "notnull",
+    "null", "offset", "on", "only", "or", "order", "outer", "overlaps",
+    "placing", "primary", "references", "returning", "right", "select",
+    "session_user", "similar", "some", "symmetric", "table", "tablesample",
+    "then", "to", "trailing", "true", "union", "unique", "user", "using",
+    "variadic", "verbose", "when", "where", "window", "with",
+];
+
+// The sql for the identifier `name`, quoted unless postgres would read
+// it back unchanged.
+fn quote_ident(name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$')
+        && !SQL_KEYWORDS.contains(&name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
+
+fn leading_digit(ident: String, prefix: &str) -> String {
+    match ident.chars().next() {
+        Some(c) if c.is_ascii_digit() => format!("{}{}", prefix, ident),
+        None => format!("{}{}", prefix, "unnamed"),
+        _ => ident,
+    }
+}
+
+fn snake_ident(name: &str) -> String {
+    let ident = leading_digit(words(name).join("_").to_lowercase(), "_");
+    if KEYWORDS.contains(&&ident[..]) {
+        format!("r#{}", ident)
+    } else {
+        ident
+    }
+}
+
+fn caps_ident(name: &str) -> String {
+    leading_digit(words(name).join("_").to_uppercase(), "_")
+}
+
+fn camel_ident(name: &str) -> String {
+    let ident = words(name).iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    leading_digit(ident, "V")
+}
+
+// Column types share the module with `Table` (and `ALL`).
+fn column_ident(column: &Column) -> String {
+    match camel_ident(&column.name) {
+        ref name if name == "Table" => "TableColumn".into(),
+        name => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = "\
+enum\tmood\thappy\tnot so happy
+table\tUsers
+column\tid\tint4\tnot null
+column\tx\ttext\tnull
+column\tuser\t_mood\tnot null
+column\tmeta\tjsonb\tnull
+key\tid
+table\tempty
+";
+
+    const GENERATED: &str = r#"// Generated by tygres-codegen; do not edit.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mood {
+    Happy,
+    NotSoHappy,
+}
+
+impl postgres::types::ToSql for Mood {
+    fn to_sql(&self, _ty: &postgres::types::Type, out: &mut Vec<u8>) -> Result<postgres::types::IsNull, Box<std::error::Error + Sync + Send>> {
+        out.extend_from_slice(match *self {
+            Mood::Happy => "happy".as_bytes(),
+            Mood::NotSoHappy => "not so happy".as_bytes(),
+        });
+        Ok(postgres::types::IsNull::No)
+    }
+
+    fn accepts(ty: &postgres::types::Type) -> bool {
+        ty.name() == "mood"
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> postgres::types::FromSql<'a> for Mood {
+    fn from_sql(_ty: &postgres::types::Type, raw: &'a [u8]) -> Result<Mood, Box<std::error::Error + Sync + Send>> {
+        match std::str::from_utf8(raw)? {
+            "happy" => Ok(Mood::Happy),
+            "not so happy" => Ok(Mood::NotSoHappy),
+            other => Err(format!("unknown mood label: {}", other).into()),
+        }
+    }
+
+    fn accepts(ty: &postgres::types::Type) -> bool {
+        ty.name() == "mood"
+    }
+}
+
+pub mod users {
+    use tygres::*;
+
+    table!(Table, "\"Users\"" => ALL {
+        (Id, ID, "id", i32, "int4"),
+        (X, X_COLUMN, "x", nullable String, "text"),
+        (User, USER, "\"user\"", Vec<super::Mood>, "mood[]"),
+        // unmapped type `jsonb`
+        (Meta, META, "meta"),
+    });
+
+    impl tygres::PrimaryKey for Table {
+        type Key = Seq![tygres::utils::ColWrap<Id>];
+        fn primary_key() -> Self::Key {
+            seq![tygres::utils::ColWrap(Id)]
+        }
+    }
+}
+
+pub mod empty {
+    use tygres::*;
+
+    table!(Table, "empty");
+}
+"#;
+
+    #[test]
+    fn generates_snapshot() {
+        let catalog = Catalog::read(SNAPSHOT.as_bytes()).unwrap();
+        assert_eq!(generate(&catalog), GENERATED);
+    }
+
+    #[test]
+    fn qualifies_tables_by_schema() {
+        let snapshot = "schema\tApp\ntable\tusers\ntable\torder\n";
+        let catalog = Catalog::read(snapshot.as_bytes()).unwrap();
+        let code = generate(&catalog);
+        assert!(code.contains(r#"table!(Table, "\"App\".users");"#), "{}", code);
+        assert!(code.contains(r#"table!(Table, "\"App\".\"order\"");"#), "{}", code);
+    }
+
+    #[test]
+    fn quotes_identifiers() {
+        assert_eq!(quote_ident("users"), "users");
+        assert_eq!(quote_ident("_id$2"), "_id$2");
+        assert_eq!(quote_ident("Users"), "\"Users\"");
+        assert_eq!(quote_ident("order"), "\"order\"");
+        assert_eq!(quote_ident("2fa"), "\"2fa\"");
+        assert_eq!(quote_ident("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::process;
+
+use postgres::{Connection, TlsMode};
+
+mod catalog;
+mod generate;
+
+use crate::catalog::{Catalog, Result};
+
+const USAGE: &str = "\
+usage: tygres-codegen (--url <url> | --snapshot <file>) [options]
+
+    --url <url>        read the catalog from a live database
+    --snapshot <file>  read the catalog from a saved snapshot
+    --schema <name>    schema to read (default: public)
+    --save <file>      also save the catalog as a snapshot
+    --out <file>       write the rust here (default: stdout)";
+
+#[derive(Default)]
+struct Args {
+    url: Option<String>,
+    snapshot: Option<String>,
+    schema: Option<String>,
+    save: Option<String>,
+    out: Option<String>,
+}
+
+fn parse_args() -> std::result::Result<Args, String> {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(flag) = iter.next() {
+        let slot = match &flag[..] {
+            "--url" => &mut args.url,
+            "--snapshot" => &mut args.snapshot,
+            "--schema" => &mut args.schema,
+            "--save" => &mut args.save,
+            "--out" => &mut args.out,
+            _ => return Err(format!("unknown argument `{}`", flag)),
+        };
+        *slot = Some(iter.next().ok_or_else(|| format!("`{}` needs a value", flag))?);
+    }
+    if args.url.is_some() == args.snapshot.is_some() {
+        return Err("exactly one of `--url` and `--snapshot` is needed".into());
+    }
+    Ok(args)
+}
+
+fn run(args: Args) -> Result<()> {
+    let catalog = match (args.url, args.snapshot) {
+        (Some(url), _) => {
+            let conn = Connection::connect(&url[..], TlsMode::None)?;
+            let schema = args.schema.as_ref().map(|s| &s[..]).unwrap_or("public");
+            Catalog::load(&conn, schema)?
+        },
+        (_, Some(path)) => Catalog::read(BufReader::new(File::open(path)?))?,
+        _ => unreachable!(),
+    };
+
+    if let Some(path) = args.save {
+        catalog.save(&mut File::create(path)?)?;
+    }
+
+    let code = generate::generate(&catalog);
+    match args.out {
+        Some(path) => File::create(path)?.write_all(code.as_bytes())?,
+        None => io::stdout().write_all(code.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(err) = run(args) {
+        eprintln!("tygres-codegen: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,220 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Lit,
+    Meta, NestedMeta, Path, PathArguments, Result, Type,
+};
+
+#[derive(Default)]
+pub struct Container {
+    pub table: Option<Path>,
+    pub columns: Option<Path>,
+    // for `derive(Table)`
+    pub name: Option<String>,
+    pub module: Option<Ident>,
+}
+
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub column: Option<Path>,
+    pub skip: bool,
+    pub default: bool,
+    // for `derive(Table)`
+    pub name: Option<String>,
+    pub sql_type: Option<String>,
+    pub primary_key: bool,
+}
+
+pub struct Field<'a> {
+    pub ident: &'a Ident,
+    pub ty: &'a Type,
+    pub attrs: FieldAttrs,
+}
+
+impl<'a> Field<'a> {
+    // The field's name without any `r#`, to derive other names from.
+    pub fn name(&self) -> String {
+        self.ident.unraw().to_string()
+    }
+}
+
+fn metas(attrs: &[Attribute]) -> Result<Vec<Meta>> {
+    let mut out = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("tygres")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => out.push(meta),
+                        other => return Err(Error::new_spanned(
+                            other, "expected `key` or `key = \"value\"`")),
+                    }
+                }
+            },
+            other => return Err(Error::new_spanned(other, "expected `#[tygres(...)]`")),
+        }
+    }
+    Ok(out)
+}
+
+fn path_value(lit: &Lit) -> Result<Path> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        other => Err(Error::new_spanned(other, "expected a string")),
+    }
+}
+
+fn str_value(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err(Error::new_spanned(other, "expected a string")),
+    }
+}
+
+impl Container {
+    pub fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut container = Container::default();
+        for meta in metas(&input.attrs)? {
+            match meta {
+                Meta::NameValue(ref nv) if nv.path.is_ident("table") => {
+                    container.table = Some(path_value(&nv.lit)?);
+                },
+                Meta::NameValue(ref nv) if nv.path.is_ident("columns") => {
+                    container.columns = Some(path_value(&nv.lit)?);
+                },
+                Meta::NameValue(ref nv) if nv.path.is_ident("name") => {
+                    container.name = Some(str_value(&nv.lit)?);
+                },
+                Meta::NameValue(ref nv) if nv.path.is_ident("module") => {
+                    match nv.lit {
+                        Lit::Str(ref s) => container.module = Some(s.parse()?),
+                        ref other => return Err(Error::new_spanned(other, "expected a string")),
+                    }
+                },
+                other => return Err(Error::new_spanned(other, "unknown tygres attribute")),
+            }
+        }
+        Ok(container)
+    }
+
+    pub fn table(&self, input: &DeriveInput) -> Result<&Path> {
+        self.table.as_ref().ok_or_else(|| Error::new_spanned(
+            &input.ident, "missing `#[tygres(table = \"...\")]`"))
+    }
+
+    pub fn column(&self, field: &Field) -> Path {
+        if let Some(ref column) = field.attrs.column {
+            return column.clone();
+        }
+        let name = Ident::new(&camel_case(&field.name()), Span::call_site());
+        match self.columns {
+            Some(ref columns) => syn::parse_quote!(#columns::#name),
+            None => name.into(),
+        }
+    }
+}
+
+impl FieldAttrs {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut field = FieldAttrs::default();
+        for meta in metas(attrs)? {
+            match meta {
+                Meta::NameValue(ref nv) if nv.path.is_ident("column") => {
+                    field.column = Some(path_value(&nv.lit)?);
+                },
+                Meta::Path(ref path) if path.is_ident("skip") => field.skip = true,
+                Meta::Path(ref path) if path.is_ident("default") => field.default = true,
+                Meta::NameValue(ref nv) if nv.path.is_ident("name") => {
+                    field.name = Some(str_value(&nv.lit)?);
+                },
+                Meta::NameValue(ref nv) if nv.path.is_ident("sql_type") => {
+                    field.sql_type = Some(str_value(&nv.lit)?);
+                },
+                Meta::Path(ref path) if path.is_ident("primary_key") => field.primary_key = true,
+                other => return Err(Error::new_spanned(other, "unknown tygres attribute")),
+            }
+        }
+        Ok(field)
+    }
+}
+
+pub fn fields(input: &DeriveInput) -> Result<Vec<Field<'_>>> {
+    let named = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref named) => named,
+            _ => return Err(Error::new_spanned(
+                &input.ident, "tygres derives need a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(
+            &input.ident, "tygres derives need a struct with named fields")),
+    };
+    named.named.iter().map(|f| Ok(Field {
+        ident: f.ident.as_ref().unwrap(),
+        ty: &f.ty,
+        attrs: FieldAttrs::parse(&f.attrs)?,
+    })).collect()
+}
+
+pub fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+pub fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, ch) in name.chars().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 { out.push('_'); }
+            out.extend(ch.to_lowercase());
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+// The `T` of a field typed `Option<T>`.
+pub fn option_inner(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(ref ty) if ty.qself.is_none() => &ty.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != "Option" {
+        return None;
+    }
+    match last.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => {
+            match args.args[0] {
+                GenericArgument::Type(ref inner) => Some(inner),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+// Nests `items` to the right, as `seq!` and `Seq!` do.
+pub fn nest<F>(items: Vec<TokenStream>, pair: F) -> TokenStream
+where F: Fn(TokenStream, TokenStream) -> TokenStream {
+    let mut items = items.into_iter().rev();
+    let last = items.next().unwrap_or_else(|| quote!(::tygres::utils::Unit));
+    items.fold(last, |acc, item| pair(item, acc))
+}
+
+pub fn seq_type(items: Vec<TokenStream>) -> TokenStream {
+    nest(items, |a, b| quote!(::tygres::utils::Seq<#a, #b>))
+}
+
+pub fn seq_value(items: Vec<TokenStream>) -> TokenStream {
+    nest(items, |a, b| quote!(::tygres::utils::Seq(#a, #b)))
+}
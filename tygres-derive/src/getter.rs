@@ -0,0 +1,84 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{DeriveInput, Error, Ident, Result};
+
+use crate::attrs::{self, Container, Field};
+
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    let container = Container::parse(input)?;
+    let fields = attrs::fields(input)?;
+    expand(input, &container, &fields)
+}
+
+pub fn expand(input: &DeriveInput, container: &Container, fields: &[Field])
+-> Result<TokenStream> {
+    let name = &input.ident;
+    let table = container.table(input)?;
+
+    let mapped: Vec<&Field> = fields.iter().filter(|f| !f.attrs.skip).collect();
+    if mapped.is_empty() {
+        return Err(Error::new_spanned(name, "no columns to select"));
+    }
+
+    let columns: Vec<_> = mapped.iter().map(|f| container.column(f)).collect();
+    let sel_ty = attrs::seq_type(
+        columns.iter().map(|c| quote!(::tygres::utils::ColWrap<#c>)).collect());
+    let sel_val = attrs::seq_value(
+        columns.iter().map(|c| quote!(::tygres::utils::ColWrap(#c))).collect());
+
+    let binds: Vec<Ident> = (0..mapped.len())
+        .map(|i| Ident::new(&format!("__sel{}", i), Span::call_site()))
+        .collect();
+    let pattern = attrs::seq_value(binds.iter().map(|b| quote!(ref #b)).collect());
+    let gets = mapped.iter().zip(&binds).map(|(f, b)| {
+        let (ident, ty) = (f.ident, f.ty);
+        quote! {
+            let (#ident, __idx): (#ty, usize) = ::tygres::Makes::get(#b, __row, __idx);
+        }
+    });
+    let try_gets = mapped.iter().zip(&binds).map(|(f, b)| {
+        let (ident, ty) = (f.ident, f.ty);
+        quote! {
+            let (#ident, __idx): (#ty, usize) = ::tygres::Makes::try_get(#b, __row, __idx)?;
+        }
+    });
+    let inits: Vec<_> = fields.iter().map(|f| {
+        let ident = f.ident;
+        if f.attrs.skip {
+            quote!(#ident: ::std::default::Default::default())
+        } else {
+            quote!(#ident)
+        }
+    }).collect();
+
+    let mut generics = input.generics.clone();
+    generics.params.insert(0, syn::parse_quote!('__a));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (plain_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #plain_generics ::tygres::Getter for #name #ty_generics #where_clause {
+            type Src = #table;
+            type Sel = #sel_ty;
+            fn getter() -> Self::Sel {
+                #sel_val
+            }
+        }
+
+        impl #impl_generics ::tygres::Makes<'__a, #sel_ty> for #name #ty_generics #where_clause {
+            fn get<__R: ::tygres::Row>(__s: &'__a #sel_ty, __row: &'__a __R, __idx: usize)
+            -> (Self, usize) {
+                let #pattern = *__s;
+                #(#gets)*
+                (#name { #(#inits),* }, __idx)
+            }
+
+            fn try_get<__R: ::tygres::Row>(__s: &'__a #sel_ty, __row: &'__a __R, __idx: usize)
+            -> ::std::result::Result<(Self, usize), ::tygres::Error> {
+                let #pattern = *__s;
+                #(#try_gets)*
+                ::std::result::Result::Ok((#name { #(#inits),* }, __idx))
+            }
+        }
+    })
+}
@@ -0,0 +1,41 @@
+extern crate proc_macro;
+
+mod attrs;
+mod getter;
+mod setter;
+mod table;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+#[proc_macro_derive(Getter, attributes(tygres))]
+pub fn derive_getter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    getter::derive(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(HasSetter, attributes(tygres))]
+pub fn derive_has_setter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    setter::derive_ref(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(HasOwnedSetter, attributes(tygres))]
+pub fn derive_has_owned_setter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    setter::derive_owned(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(Table, attributes(tygres))]
+pub fn derive_table(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    table::derive(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
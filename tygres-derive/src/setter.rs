@@ -0,0 +1,183 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Error, Result};
+
+use crate::attrs::{self, Container, Field};
+
+// Fields marked `default` are set to `DEFAULT`, and `skip`ped ones are
+// left out.  The static setters (`HasSetter`, `HasOwnedSetter`) set
+// every other column, so `Option` fields write nulls; the value setters
+// (`RefSetter`, `ValSetter`) leave out the `None` fields instead.
+
+fn mapped<'a, 'b>(input: &DeriveInput, fields: &'b [Field<'a>]) -> Result<Vec<&'b Field<'a>>> {
+    let mapped: Vec<_> = fields.iter().filter(|f| !f.attrs.skip).collect();
+    if mapped.is_empty() {
+        return Err(Error::new_spanned(&input.ident, "no columns to set"));
+    }
+    Ok(mapped)
+}
+
+fn column_setter(container: &Container, field: &Field) -> (TokenStream, TokenStream) {
+    let column = container.column(field);
+    if field.attrs.default {
+        (
+            quote!(::tygres::setters::SetDefault<::tygres::utils::ColWrap<#column>>),
+            quote!(::tygres::setters::SetDefault(::tygres::utils::ColWrap(#column))),
+        )
+    } else {
+        (
+            quote!(::tygres::utils::ColWrap<#column>),
+            quote!(::tygres::utils::ColWrap(#column)),
+        )
+    }
+}
+
+pub fn derive_ref(input: &DeriveInput) -> Result<TokenStream> {
+    let container = Container::parse(input)?;
+    let fields = attrs::fields(input)?;
+    expand_ref(input, &container, &fields)
+}
+
+pub fn expand_ref(input: &DeriveInput, container: &Container, fields: &[Field])
+-> Result<TokenStream> {
+    let mapped = mapped(input, fields)?;
+    let name = &input.ident;
+
+    let (set_tys, set_vals): (Vec<_>, Vec<_>) = mapped.iter()
+        .map(|f| column_setter(container, f))
+        .unzip();
+    let (val_tys, vals): (Vec<_>, Vec<_>) = mapped.iter().map(|f| {
+        let (ident, ty) = (f.ident, f.ty);
+        if f.attrs.default {
+            (quote!(::tygres::utils::Unit), quote!(::tygres::utils::Unit))
+        } else {
+            (quote!(&'__a #ty), quote!(&self.#ident))
+        }
+    }).unzip();
+    let (out_tys, outs): (Vec<_>, Vec<_>) = mapped.iter().map(|f| {
+        let ident = f.ident;
+        let column = container.column(f);
+        if f.attrs.default {
+            return column_setter(container, f);
+        }
+        match attrs::option_inner(f.ty) {
+            Some(inner) => (
+                quote!(::tygres::setters::OptValue<
+                    ::tygres::utils::ColWrap<#column>, &'__a Option<#inner>>),
+                quote!(::tygres::setters::OptValue(::tygres::utils::ColWrap(#column), &self.#ident)),
+            ),
+            None => {
+                let ty = f.ty;
+                (
+                    quote!(::tygres::setters::RefValue<'__a, ::tygres::utils::ColWrap<#column>, #ty>),
+                    quote!(::tygres::setters::RefValue(::tygres::utils::ColWrap(#column), &self.#ident)),
+                )
+            },
+        }
+    }).unzip();
+
+    let set_ty = attrs::seq_type(set_tys);
+    let set_val = attrs::seq_value(set_vals);
+    let val_ty = attrs::seq_type(val_tys);
+    let val = attrs::seq_value(vals);
+    let out_ty = attrs::seq_type(out_tys);
+    let out = attrs::seq_value(outs);
+
+    let mut generics = input.generics.clone();
+    generics.params.insert(0, syn::parse_quote!('__a));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::tygres::HasSetter<'__a> for #name #ty_generics #where_clause {
+            type Val = #val_ty;
+            type Set = #set_ty;
+            fn setter() -> Self::Set {
+                #set_val
+            }
+            fn as_value(&'__a self) -> Self::Val {
+                #val
+            }
+        }
+
+        impl #impl_generics ::tygres::RefSetter<'__a> for #name #ty_generics #where_clause {
+            type Out = #out_ty;
+            fn as_setter(&'__a self) -> Self::Out {
+                #out
+            }
+        }
+    })
+}
+
+pub fn derive_owned(input: &DeriveInput) -> Result<TokenStream> {
+    let container = Container::parse(input)?;
+    let fields = attrs::fields(input)?;
+    expand_owned(input, &container, &fields)
+}
+
+pub fn expand_owned(input: &DeriveInput, container: &Container, fields: &[Field])
+-> Result<TokenStream> {
+    let mapped = mapped(input, fields)?;
+    let name = &input.ident;
+
+    let (set_tys, set_vals): (Vec<_>, Vec<_>) = mapped.iter()
+        .map(|f| column_setter(container, f))
+        .unzip();
+    let (val_tys, vals): (Vec<_>, Vec<_>) = mapped.iter().map(|f| {
+        let (ident, ty) = (f.ident, f.ty);
+        if f.attrs.default {
+            (quote!(::tygres::utils::Unit), quote!(::tygres::utils::Unit))
+        } else {
+            (quote!(#ty), quote!(self.#ident))
+        }
+    }).unzip();
+    let (out_tys, outs): (Vec<_>, Vec<_>) = mapped.iter().map(|f| {
+        let ident = f.ident;
+        let column = container.column(f);
+        if f.attrs.default {
+            return column_setter(container, f);
+        }
+        match attrs::option_inner(f.ty) {
+            Some(inner) => (
+                quote!(::tygres::setters::OptValue<::tygres::utils::ColWrap<#column>, Option<#inner>>),
+                quote!(::tygres::setters::OptValue(::tygres::utils::ColWrap(#column), self.#ident)),
+            ),
+            None => {
+                let ty = f.ty;
+                (
+                    quote!(::tygres::utils::WithValue<::tygres::utils::ColWrap<#column>, #ty>),
+                    quote!(::tygres::utils::WithValue(::tygres::utils::ColWrap(#column), self.#ident)),
+                )
+            },
+        }
+    }).unzip();
+
+    let set_ty = attrs::seq_type(set_tys);
+    let set_val = attrs::seq_value(set_vals);
+    let val_ty = attrs::seq_type(val_tys);
+    let val = attrs::seq_value(vals);
+    let out_ty = attrs::seq_type(out_tys);
+    let out = attrs::seq_value(outs);
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::tygres::HasOwnedSetter for #name #ty_generics #where_clause {
+            type Val = #val_ty;
+            type Set = #set_ty;
+            fn setter() -> Self::Set {
+                #set_val
+            }
+            fn into_value(self) -> Self::Val {
+                #val
+            }
+        }
+
+        impl #impl_generics ::tygres::ValSetter for #name #ty_generics #where_clause {
+            type Out = #out_ty;
+            fn as_setter(self) -> Self::Out {
+                #out
+            }
+        }
+    })
+}
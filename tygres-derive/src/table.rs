@@ -0,0 +1,221 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{DeriveInput, Error, Ident, LitStr, Result};
+use syn::ext::IdentExt;
+
+use crate::{getter, setter};
+use crate::attrs::{self, Container, Field};
+
+// The name of an item declared for a field, which must be an identifier
+// other than the module's own `Table` and `ALL`.
+fn declared(f: &Field, name: String) -> Result<Ident> {
+    let error = |what| Error::new_spanned(
+        f.ident, format!("field `{}` {} `{}`; rename or skip it", f.name(), what, name));
+    if name == "Table" || name == "ALL" {
+        return Err(error("collides with the table's"));
+    }
+    syn::parse_str(&name).map_err(|_| error("can not be declared as"))
+}
+
+// Declares the table in a module named by `module` (by default the sql
+// name, which defaults to the snake cased struct name): a `Table`
+// source, and a column type and `ColWrap` constant per field, as
+// `table!` and `column!` would.  The struct itself gets the `Getter`
+// and setters for the full row.
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics, "derive(Table) does not support generics"));
+    }
+    let parsed = Container::parse(input)?;
+    if parsed.table.is_some() || parsed.columns.is_some() {
+        return Err(Error::new_spanned(
+            &input.ident, "derive(Table) declares its own `table` and `columns`"));
+    }
+    let fields = attrs::fields(input)?;
+    if let Some(f) = fields.iter().find(|f| f.attrs.column.is_some()) {
+        return Err(Error::new_spanned(
+            f.ident, "derive(Table) declares its own columns; use `name` instead"));
+    }
+
+    let name = parsed.name.clone()
+        .unwrap_or_else(|| attrs::snake_case(&input.ident.unraw().to_string()));
+    let module = match parsed.module.clone() {
+        Some(module) => module,
+        None => syn::parse_str::<Ident>(&name).map_err(|_| Error::new_spanned(
+            &input.ident, format!("`{}` is not a module name; set `module`", name)))?,
+    };
+    let container = Container {
+        table: Some(syn::parse_quote!(#module::Table)),
+        columns: Some(module.clone().into()),
+        ..parsed
+    };
+    let vis = &input.vis;
+    let table_name = LitStr::new(&name, Span::call_site());
+
+    let mapped: Vec<_> = fields.iter().filter(|f| !f.attrs.skip).collect();
+    let mut columns = vec![];
+    let mut consts = vec![];
+    let mut decls = vec![];
+    for f in &mapped {
+        let column = declared(f, attrs::camel_case(&f.name()))?;
+        let caps = declared(f, f.name().to_uppercase())?;
+        let column_name = LitStr::new(
+            &f.attrs.name.clone().unwrap_or_else(|| f.name()),
+            Span::call_site());
+        let ty = f.ty;
+        let inner = attrs::option_inner(ty);
+        let nullable = inner.is_some();
+        let sql_type = match f.attrs.sql_type {
+            Some(ref sql_type) => {
+                let sql_type = LitStr::new(sql_type, Span::call_site());
+                quote!(#sql_type)
+            },
+            None => {
+                let inner = inner.unwrap_or(ty);
+                quote!(<#inner as ::tygres::types::SqlType>::NAME)
+            },
+        };
+        let nullable_impl = if nullable {
+            // `OptValue` sets the present values of `Option` fields
+            let inner = inner.unwrap();
+            quote! {
+                impl ::tygres::source::Nullable for #column {}
+                ::tygres::takes!(#column, #inner);
+            }
+        } else {
+            quote!()
+        };
+
+        decls.push(quote! {
+            pub struct #column;
+            pub const #caps: ::tygres::utils::ColWrap<#column> = ::tygres::utils::ColWrap(#column);
+
+            impl ::tygres::Column<Table> for #column {
+                fn push_name(&self, buf: &mut String) {
+                    buf.push_str(#column_name);
+                }
+            }
+
+            impl ::tygres::source::Typed for #column {
+                type Type = #ty;
+                const SQL_TYPE: &'static str = #sql_type;
+                const NULLABLE: bool = #nullable;
+            }
+
+            #nullable_impl
+            ::tygres::takes!(#column, #ty);
+            ::tygres::makes!(#column, #ty);
+        });
+        columns.push((column, f.attrs.primary_key));
+        consts.push(caps);
+    }
+
+    let all_ty = attrs::seq_type(columns.iter()
+        .map(|(c, _)| quote!(::tygres::utils::ColWrap<#c>)).collect());
+    let all_val = attrs::seq_value(consts.iter().map(|c| quote!(#c)).collect());
+
+    let keys: Vec<_> = columns.iter().filter(|(_, key)| *key).map(|(c, _)| c).collect();
+    let primary_key = if keys.is_empty() {
+        quote!()
+    } else {
+        let key_ty = attrs::seq_type(keys.iter()
+            .map(|c| quote!(::tygres::utils::ColWrap<#c>)).collect());
+        let key_val = attrs::seq_value(keys.iter()
+            .map(|c| quote!(::tygres::utils::ColWrap(#c))).collect());
+        quote! {
+            impl ::tygres::source::PrimaryKey for Table {
+                type Key = #key_ty;
+                fn primary_key() -> Self::Key {
+                    #key_val
+                }
+            }
+        }
+    };
+
+    let getter = getter::expand(input, &container, &fields)?;
+    let ref_setter = setter::expand_ref(input, &container, &fields)?;
+    let owned_setter = setter::expand_owned(input, &container, &fields)?;
+
+    Ok(quote! {
+        #vis mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub struct Table;
+
+            impl ::tygres::Source for Table {
+                type Set = ::tygres::utils::Unit;
+
+                fn push_source(&self, buf: &mut String) {
+                    buf.push_str(#table_name);
+                }
+
+                fn into_set(self) -> Self::Set {
+                    ::tygres::utils::Unit
+                }
+            }
+
+            impl ::tygres::source::Named for Table {
+                const NAME: &'static str = #table_name;
+            }
+
+            #(#decls)*
+
+            pub const ALL: #all_ty = #all_val;
+
+            #primary_key
+        }
+
+        #getter
+        #ref_setter
+        #owned_setter
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: DeriveInput) -> String {
+        derive(&input).expect_err("derive(Table) should fail").to_string()
+    }
+
+    #[test]
+    fn requires_a_module_for_qualified_names() {
+        let message = error(syn::parse_quote! {
+            #[tygres(name = "app.users")]
+            struct User { id: i32 }
+        });
+        assert_eq!(message, "`app.users` is not a module name; set `module`");
+        assert!(derive(&syn::parse_quote! {
+            #[tygres(name = "app.users", module = "users")]
+            struct User { id: i32 }
+        }).is_ok());
+    }
+
+    #[test]
+    fn rejects_fields_declaring_table_items() {
+        let message = error(syn::parse_quote! {
+            struct User { id: i32, all: bool }
+        });
+        assert_eq!(message, "field `all` collides with the table's `ALL`; rename or skip it");
+        let message = error(syn::parse_quote! {
+            struct User { id: i32, table: String }
+        });
+        assert_eq!(message, "field `table` collides with the table's `Table`; rename or skip it");
+        assert!(derive(&syn::parse_quote! {
+            struct User { id: i32, #[tygres(skip)] all: bool }
+        }).is_ok());
+    }
+
+    #[test]
+    fn unraws_field_names() {
+        let tokens = derive(&syn::parse_quote! {
+            struct Event { r#type: String }
+        }).unwrap().to_string();
+        assert!(tokens.contains("pub struct Type ;"), "{}", tokens);
+        assert!(tokens.contains("pub const TYPE :"), "{}", tokens);
+        assert!(tokens.contains("\"type\""), "{}", tokens);
+    }
+}